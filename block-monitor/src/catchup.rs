@@ -0,0 +1,350 @@
+use crate::rpc_pool::RpcPool;
+use alloy::rpc::types::{Filter, Log};
+use eyre::Result;
+use futures_util::{future::BoxFuture, stream, StreamExt};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+/// Attempts at a range before it's left to block-by-block processing
+const RANGE_ATTEMPTS: u32 = 3;
+const RANGE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Something that answers `eth_getLogs`.
+pub trait LogSource: Send + Sync {
+    fn get_logs<'a>(&'a self, filter: &'a Filter) -> BoxFuture<'a, Result<Vec<Log>>>;
+}
+
+/// Through the pool, so range queries get the same quorum check as
+/// single blocks.
+impl LogSource for RpcPool {
+    fn get_logs<'a>(&'a self, filter: &'a Filter) -> BoxFuture<'a, Result<Vec<Log>>> {
+        Box::pin(RpcPool::get_logs(self, filter))
+    }
+}
+
+/// Logs for a block range, and the parts of it that couldn't be fetched.
+#[derive(Default)]
+pub struct RangeLogs {
+    /// Logs from every block outside `failed`, in block and log order
+    pub logs: Vec<Log>,
+    /// Sub-ranges whose requests kept failing, with the last error
+    pub failed: Vec<(u64, u64, eyre::Report)>,
+}
+
+/// Fetches logs for block ranges with `eth_getLogs`, splitting ranges the
+/// provider rejects as too large and running a bounded number of requests at
+/// once.
+pub struct RangeFetcher {
+    /// Current chunk size; shrinks when the provider pushes back and grows
+    /// back towards `max_chunk_size` after clean windows
+    chunk_size: AtomicU64,
+    max_chunk_size: u64,
+    concurrency: usize,
+    retry_delay: Duration,
+}
+
+impl RangeFetcher {
+    pub fn new(max_chunk_size: u64, concurrency: usize) -> Self {
+        let max_chunk_size = max_chunk_size.max(1);
+        Self {
+            chunk_size: AtomicU64::new(max_chunk_size),
+            max_chunk_size,
+            concurrency: concurrency.max(1),
+            retry_delay: RANGE_RETRY_DELAY,
+        }
+    }
+
+    /// Number of blocks covered by one round of concurrent requests.
+    pub fn window_size(&self) -> u64 {
+        self.chunk_size.load(Ordering::Relaxed) * self.concurrency as u64
+    }
+
+    /// All logs matching `filter` between `from` and `to` inclusive. A chunk
+    /// that keeps failing doesn't affect the others; its range is reported
+    /// in [`RangeLogs::failed`] instead.
+    pub async fn fetch(
+        &self,
+        source: &dyn LogSource,
+        filter: &Filter,
+        from: u64,
+        to: u64,
+    ) -> RangeLogs {
+        let chunk_size = self.chunk_size.load(Ordering::Relaxed);
+        let chunks: Vec<(u64, u64)> = (from..=to)
+            .step_by(chunk_size as usize)
+            .map(|start| (start, (start + chunk_size - 1).min(to)))
+            .collect();
+
+        // `buffered` keeps results in chunk order even though requests overlap
+        let results: Vec<(RangeLogs, bool)> = stream::iter(chunks)
+            .map(|(start, end)| self.fetch_chunk(source, filter, start, end))
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+
+        let split = results.iter().any(|(_, split)| *split);
+        if !split && chunk_size < self.max_chunk_size {
+            let grown = (chunk_size * 2).min(self.max_chunk_size);
+            self.chunk_size.store(grown, Ordering::Relaxed);
+            debug!("Range query chunk size grown to {} blocks", grown);
+        }
+
+        let mut range = RangeLogs::default();
+        for (mut chunk, _) in results {
+            range.logs.append(&mut chunk.logs);
+            range.failed.append(&mut chunk.failed);
+        }
+        range
+            .logs
+            .sort_by_key(|log| (log.block_number, log.log_index));
+        range
+    }
+
+    /// Fetch one chunk, halving it until the provider accepts the request
+    /// and retrying other failures a few times. The flag is true if the
+    /// chunk had to be split.
+    async fn fetch_chunk(
+        &self,
+        source: &dyn LogSource,
+        filter: &Filter,
+        from: u64,
+        to: u64,
+    ) -> (RangeLogs, bool) {
+        let mut pending = vec![(from, to)];
+        let mut range = RangeLogs::default();
+        let mut split = false;
+
+        // Depth-first so sub-ranges come back in block order
+        while let Some((start, end)) = pending.pop() {
+            let range_filter = filter.clone().from_block(start).to_block(end);
+            let mut attempt = 1;
+            loop {
+                match source.get_logs(&range_filter).await {
+                    Ok(mut logs) => range.logs.append(&mut logs),
+                    Err(e) if start < end && is_range_too_large(&format!("{:#}", e)) => {
+                        let mid = start + (end - start) / 2;
+                        let halved = (end - start + 1).div_ceil(2);
+                        self.chunk_size.fetch_min(halved, Ordering::Relaxed);
+                        warn!(
+                            "Provider rejected logs for blocks {}-{} ({:#}); splitting and reducing chunk size to {}",
+                            start, end, e, halved
+                        );
+                        pending.push((mid + 1, end));
+                        pending.push((start, mid));
+                        split = true;
+                    }
+                    Err(e) if attempt < RANGE_ATTEMPTS => {
+                        debug!(
+                            "Range query for blocks {}-{} failed ({:#}); retrying",
+                            start, end, e
+                        );
+                        tokio::time::sleep(self.retry_delay).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    Err(e) => range.failed.push((start, end, e)),
+                }
+                break;
+            }
+        }
+
+        (range, split)
+    }
+}
+
+/// Providers word "your range is too big" in many different ways. Only
+/// their documented range and result-size messages count; rate limits and
+/// timeouts are retried as they are, since splitting would only send more
+/// requests.
+fn is_range_too_large(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    [
+        // geth, Infura
+        "query returned more than",
+        // Alchemy
+        "response size exceeded",
+        // QuickNode
+        "getlogs is limited to a",
+        // Block range caps elsewhere
+        "exceed maximum block range",
+        "block range greater than",
+        "range is too large",
+        "range too large",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Progress through `from..=to` once `done` is processed, with an estimate
+/// of the time left at the rate so far, e.g. `1500 blocks remaining, 25.0%
+/// done, ETA 2m30s`.
+pub fn progress(from: u64, done: u64, to: u64, elapsed: Duration) -> String {
+    let total = to - from + 1;
+    let processed = done + 1 - from;
+    let remaining = to - done;
+    let eta = elapsed.as_secs_f64() / processed as f64 * remaining as f64;
+    let eta = eta.round() as u64;
+    format!(
+        "{} blocks remaining, {:.1}% done, ETA {}m{:02}s",
+        remaining,
+        processed as f64 * 100.0 / total as f64,
+        eta / 60,
+        eta % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// One log per block, from a provider that rejects ranges wider than
+    /// `max_range` and always fails for `broken` blocks.
+    struct FakeSource {
+        max_range: u64,
+        broken: Option<u64>,
+        requests: Mutex<Vec<(u64, u64)>>,
+    }
+
+    impl FakeSource {
+        fn new(max_range: u64) -> Self {
+            Self {
+                max_range,
+                broken: None,
+                requests: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    impl LogSource for FakeSource {
+        fn get_logs<'a>(&'a self, filter: &'a Filter) -> BoxFuture<'a, Result<Vec<Log>>> {
+            Box::pin(async move {
+                let from = filter.get_from_block().unwrap();
+                let to = filter.get_to_block().unwrap();
+                self.requests.lock().unwrap().push((from, to));
+                // Later chunks answer first
+                tokio::time::sleep(Duration::from_millis(100u64.saturating_sub(from))).await;
+                if to - from + 1 > self.max_range {
+                    eyre::bail!("query returned more than 10000 results");
+                }
+                if self
+                    .broken
+                    .is_some_and(|block| (from..=to).contains(&block))
+                {
+                    eyre::bail!("connection reset");
+                }
+                Ok((from..=to)
+                    .map(|block| Log {
+                        block_number: Some(block),
+                        log_index: Some(0),
+                        ..Default::default()
+                    })
+                    .collect())
+            })
+        }
+    }
+
+    fn fetcher(chunk_size: u64, concurrency: usize) -> RangeFetcher {
+        RangeFetcher {
+            retry_delay: Duration::ZERO,
+            ..RangeFetcher::new(chunk_size, concurrency)
+        }
+    }
+
+    fn blocks(range: &RangeLogs) -> Vec<u64> {
+        range
+            .logs
+            .iter()
+            .filter_map(|log| log.block_number)
+            .collect()
+    }
+
+    #[test]
+    fn range_errors_are_recognised() {
+        assert!(is_range_too_large(
+            "server returned an error response: error code -32005: query returned more than 10000 results"
+        ));
+        assert!(is_range_too_large("Log response size exceeded."));
+        assert!(is_range_too_large(
+            "eth_getLogs is limited to a 10000 range"
+        ));
+        assert!(is_range_too_large("Block Range Too Large"));
+        assert!(!is_range_too_large("429 Too Many Requests"));
+        assert!(!is_range_too_large("request timed out"));
+    }
+
+    #[tokio::test]
+    async fn rejected_chunks_are_halved() {
+        let source = FakeSource::new(3);
+        let fetcher = fetcher(8, 1);
+
+        let range = fetcher.fetch(&source, &Filter::new(), 10, 17).await;
+
+        assert_eq!(blocks(&range), (10..=17).collect::<Vec<_>>());
+        assert!(range.failed.is_empty());
+        assert_eq!(fetcher.chunk_size.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            *source.requests.lock().unwrap(),
+            [
+                (10, 17),
+                (10, 13),
+                (10, 11),
+                (12, 13),
+                (14, 17),
+                (14, 15),
+                (16, 17)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn chunk_size_grows_back_after_a_clean_window() {
+        let source = FakeSource::new(100);
+        let fetcher = fetcher(8, 1);
+        fetcher.chunk_size.store(2, Ordering::Relaxed);
+
+        fetcher.fetch(&source, &Filter::new(), 0, 3).await;
+
+        assert_eq!(fetcher.chunk_size.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn concurrent_chunks_come_back_in_block_order() {
+        let source = FakeSource::new(100);
+        let fetcher = fetcher(2, 4);
+
+        let range = fetcher.fetch(&source, &Filter::new(), 0, 7).await;
+
+        assert_eq!(blocks(&range), (0..=7).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn failed_chunks_leave_the_others_alone() {
+        let source = FakeSource {
+            broken: Some(5),
+            ..FakeSource::new(100)
+        };
+        let fetcher = fetcher(2, 4);
+
+        let range = fetcher.fetch(&source, &Filter::new(), 0, 7).await;
+
+        assert_eq!(blocks(&range), [0, 1, 2, 3, 6, 7]);
+        let failed: Vec<_> = range
+            .failed
+            .iter()
+            .map(|(from, to, _)| (*from, *to))
+            .collect();
+        assert_eq!(failed, [(4, 5)]);
+        let attempts = source
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|range| **range == (4, 5))
+            .count();
+        assert_eq!(attempts, RANGE_ATTEMPTS as usize);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::config::Source;
+use eyre::{bail, Result};
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// Chains that can be enabled by name in `CHAINS`, with the chain ID their
+/// RPC endpoints must report and a public RPC to fall back on.
+const KNOWN_CHAINS: &[(&str, u64, &str)] = &[
+    ("base", 8453, "https://mainnet.base.org"),
+    ("ethereum", 1, "https://ethereum-rpc.publicnode.com"),
+    ("arbitrum", 42161, "https://arb1.arbitrum.io/rpc"),
+    ("optimism", 10, "https://mainnet.optimism.io"),
+    ("polygon", 137, "https://polygon-rpc.com"),
+];
+
+/// Settings that only make sense for one chain. They're read unprefixed only
+/// when a single chain is configured, so single-chain deployments keep
+/// working unchanged.
+const CHAIN_SPECIFIC: &[&str] = &[
+    "RPC_URLS",
+    "RPC_URL",
+    "WS_RPC_URL",
+    "CHECKPOINT_FILE",
+    "START_BLOCK",
+];
+
+/// One chain to monitor. Every setting is looked up as `<NAME>_<KEY>` first,
+/// e.g. `ARBITRUM_RPC_URL`, so chains can be configured independently.
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    source: Arc<Source>,
+    pub name: String,
+    /// Chain ID the RPC endpoints must serve, for chains we know
+    pub expected_chain_id: Option<u64>,
+    prefix: String,
+    default_rpc_url: Option<&'static str>,
+    /// Whether chain-specific settings may be read without the prefix
+    only_chain: bool,
+}
+
+impl ChainConfig {
+    /// Chains listed in `CHAINS` (comma-separated, default `base`).
+    pub fn from_source(source: &Arc<Source>) -> Result<Vec<Self>> {
+        let names: Vec<String> = source
+            .get("CHAINS")
+            .unwrap_or_else(|| "base".to_string())
+            .split(',')
+            .map(|name| name.trim().to_ascii_lowercase())
+            .filter(|name| !name.is_empty())
+            .collect();
+
+        if names.is_empty() {
+            bail!("CHAINS is empty; set it to a comma-separated list such as base,ethereum");
+        }
+
+        let mut chains: Vec<Self> = Vec::with_capacity(names.len());
+        for name in &names {
+            if chains.iter().any(|chain| &chain.name == name) {
+                bail!("chain {} is listed twice in CHAINS", name);
+            }
+            let known = KNOWN_CHAINS.iter().find(|(known, _, _)| known == name);
+            chains.push(Self {
+                source: source.clone(),
+                name: name.clone(),
+                expected_chain_id: known.map(|(_, chain_id, _)| *chain_id),
+                prefix: name.to_ascii_uppercase().replace('-', "_"),
+                default_rpc_url: known.map(|(_, _, url)| *url),
+                only_chain: names.len() == 1,
+            });
+        }
+        Ok(chains)
+    }
+
+    /// `<NAME>_<key>`, falling back to the unprefixed variable for settings
+    /// shared between chains.
+    pub fn var(&self, key: &str) -> Option<String> {
+        self.source
+            .get(&format!("{}_{}", self.prefix, key))
+            .or_else(|| {
+                (self.only_chain || !CHAIN_SPECIFIC.contains(&key))
+                    .then(|| self.source.get(key))
+                    .flatten()
+            })
+    }
+
+    /// [`var`](Self::var) parsed, or `default` when it isn't set.
+    pub fn parse<T: FromStr>(&self, key: &str, default: T) -> Result<T>
+    where
+        T::Err: fmt::Display,
+    {
+        match self.var(key) {
+            Some(value) => value
+                .parse()
+                .map_err(|e| eyre::eyre!("invalid {} {:?}: {}", key, value, e)),
+            None => Ok(default),
+        }
+    }
+
+    /// A true/false (or 1/0) setting.
+    pub fn flag(&self, key: &str, default: bool) -> Result<bool> {
+        match self.var(key).as_deref() {
+            Some("true" | "1") => Ok(true),
+            Some("false" | "0") => Ok(false),
+            Some(value) => bail!("invalid {} {:?}: expected true or false", key, value),
+            None => Ok(default),
+        }
+    }
+
+    /// RPC endpoints for this chain: `RPC_URLS` (comma-separated), then
+    /// `RPC_URL`, then the chain's public RPC.
+    pub fn rpc_urls(&self) -> Result<Vec<String>> {
+        let urls: Vec<String> = match self.var("RPC_URLS") {
+            Some(urls) => urls
+                .split(',')
+                .map(|url| url.trim().to_string())
+                .filter(|url| !url.is_empty())
+                .collect(),
+            None => self
+                .var("RPC_URL")
+                // Fallback for backward compatibility
+                .or_else(|| {
+                    self.only_chain
+                        .then(|| self.source.get("ALCHEMY_RPC_URL"))
+                        .flatten()
+                })
+                .or_else(|| self.default_rpc_url.map(str::to_string))
+                .into_iter()
+                .collect(),
+        };
+
+        if urls.is_empty() {
+            bail!(
+                "no RPC endpoint for chain {}; set {}_RPC_URL",
+                self.name,
+                self.prefix
+            );
+        }
+        if urls.iter().any(|url| {
+            url.contains("YOUR_API_KEY")
+                || url.contains("YOUR_PROJECT_ID")
+                || url.contains("YOUR_KEY")
+        }) {
+            bail!(
+                "{}_RPC_URL still contains a placeholder API key; use your own endpoint from a provider like Alchemy, Infura or QuickNode, or the chain's public RPC",
+                self.prefix
+            );
+        }
+        Ok(urls)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configure(settings: &[(&str, &str)]) -> Result<Vec<ChainConfig>> {
+        let overrides = settings
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        ChainConfig::from_source(&Arc::new(Source::load(None, overrides).unwrap()))
+    }
+
+    #[test]
+    fn chains_come_from_the_chains_setting() {
+        let chains = configure(&[("CHAINS", " Base, arbitrum,,my-l2 ")]).unwrap();
+        let names: Vec<_> = chains.iter().map(|chain| chain.name.as_str()).collect();
+        assert_eq!(names, ["base", "arbitrum", "my-l2"]);
+        assert_eq!(chains[0].expected_chain_id, Some(8453));
+        assert_eq!(chains[2].expected_chain_id, None);
+        assert_eq!(chains[2].prefix, "MY_L2");
+
+        assert!(configure(&[("CHAINS", "base,BASE")]).is_err());
+        assert!(configure(&[("CHAINS", " , ")]).is_err());
+    }
+
+    #[test]
+    fn prefixed_settings_win() {
+        let chains = configure(&[
+            ("CHAINS", "base,arbitrum"),
+            ("BASE_CATCHUP_CHUNK_SIZE", "100"),
+            ("CATCHUP_CHUNK_SIZE", "500"),
+        ])
+        .unwrap();
+        assert_eq!(chains[0].parse("CATCHUP_CHUNK_SIZE", 0).unwrap(), 100);
+        // Shared settings apply to every chain without their own
+        assert_eq!(chains[1].parse("CATCHUP_CHUNK_SIZE", 0).unwrap(), 500);
+    }
+
+    #[test]
+    fn chain_specific_settings_need_a_prefix_with_several_chains() {
+        let settings = [
+            ("RPC_URL", "https://rpc.example.com"),
+            ("START_BLOCK", "100"),
+        ];
+        let single = configure(&[&[("CHAINS", "polygon")], &settings[..]].concat()).unwrap();
+        assert_eq!(single[0].var("START_BLOCK").as_deref(), Some("100"));
+
+        let several =
+            configure(&[&[("CHAINS", "polygon,optimism")], &settings[..]].concat()).unwrap();
+        for chain in &several {
+            for key in CHAIN_SPECIFIC {
+                assert_eq!(chain.var(key), None, "{} {}", chain.name, key);
+            }
+        }
+    }
+
+    #[test]
+    fn rpc_urls_prefer_the_list_then_the_single_url_then_the_public_rpc() {
+        let chain = |settings: &[(&str, &str)]| {
+            let mut chains = configure(&[&[("CHAINS", "polygon")], settings].concat()).unwrap();
+            chains.remove(0)
+        };
+
+        assert_eq!(
+            chain(&[
+                (
+                    "POLYGON_RPC_URLS",
+                    "https://a.example.com, ,https://b.example.com"
+                ),
+                ("POLYGON_RPC_URL", "https://c.example.com"),
+            ])
+            .rpc_urls()
+            .unwrap(),
+            ["https://a.example.com", "https://b.example.com"]
+        );
+        assert_eq!(
+            chain(&[("POLYGON_RPC_URL", "https://c.example.com")])
+                .rpc_urls()
+                .unwrap(),
+            ["https://c.example.com"]
+        );
+        assert_eq!(
+            chain(&[("ALCHEMY_RPC_URL", "https://d.example.com")])
+                .rpc_urls()
+                .unwrap(),
+            ["https://d.example.com"]
+        );
+        assert_eq!(
+            chain(&[("POLYGON_RPC_URLS", "")])
+                .rpc_urls()
+                .unwrap_err()
+                .to_string(),
+            "no RPC endpoint for chain polygon; set POLYGON_RPC_URL"
+        );
+        assert!(
+            chain(&[("POLYGON_RPC_URL", "https://x.example.com/YOUR_API_KEY")])
+                .rpc_urls()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn unknown_chains_need_an_rpc_url() {
+        let chains = configure(&[("CHAINS", "my-l2,polygon")]).unwrap();
+        assert!(chains[0].rpc_urls().is_err());
+        // ALCHEMY_RPC_URL only stands in for a single chain
+        let several = configure(&[
+            ("CHAINS", "my-l2,polygon"),
+            ("ALCHEMY_RPC_URL", "https://d.example.com"),
+        ])
+        .unwrap();
+        assert!(several[0].rpc_urls().is_err());
+        assert_eq!(several[1].rpc_urls().unwrap(), ["https://polygon-rpc.com"]);
+    }
+
+    #[test]
+    fn flags_and_numbers_are_checked() {
+        let chains = configure(&[
+            ("CHAINS", "polygon"),
+            ("RPC_QUORUM", "1"),
+            ("DECODE_APPROVALS", "yes"),
+            ("REORG_DEPTH", "deep"),
+        ])
+        .unwrap();
+        assert!(chains[0].flag("RPC_QUORUM", false).unwrap());
+        assert!(chains[0].flag("TOKEN_METADATA_STRICT", true).unwrap());
+        assert!(chains[0].flag("DECODE_APPROVALS", false).is_err());
+        assert!(chains[0].parse("REORG_DEPTH", 64u64).is_err());
+    }
+}
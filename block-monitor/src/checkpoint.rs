@@ -0,0 +1,334 @@
+use crate::{gaps::Gap, redis_conn::RedisConnection};
+use eyre::{bail, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Where the last fully processed block, and any blocks that failed to
+/// process, are persisted between restarts.
+pub enum CheckpointStore {
+    Redis {
+        conn: RedisConnection,
+        key: String,
+        gaps_key: String,
+        unconfirmed_key: String,
+    },
+    File {
+        path: PathBuf,
+    },
+}
+
+/// Persisted monitor progress.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub last_block: u64,
+    #[serde(default)]
+    pub gaps: Vec<Gap>,
+    /// Lowest block with events still waiting to be published as
+    /// `confirmed`. Those are only held in memory, so a restart scans again
+    /// from here to pick them up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unconfirmed_from: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile {
+    chain_id: u64,
+    #[serde(flatten)]
+    checkpoint: Checkpoint,
+}
+
+impl CheckpointStore {
+    pub fn redis(conn: RedisConnection, chain_id: u64) -> Self {
+        Self::Redis {
+            conn,
+            key: format!("block-monitor:checkpoint:{}", chain_id),
+            gaps_key: format!("block-monitor:gaps:{}", chain_id),
+            unconfirmed_key: format!("block-monitor:unconfirmed:{}", chain_id),
+        }
+    }
+
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self::File { path: path.into() }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Redis { key, .. } => format!("redis key {}", key),
+            Self::File { path } => format!("file {}", path.display()),
+        }
+    }
+
+    pub async fn load(&self, chain_id: u64) -> Result<Option<Checkpoint>> {
+        match self {
+            Self::Redis {
+                conn,
+                key,
+                gaps_key,
+                unconfirmed_key,
+            } => {
+                let Some(mut conn) = conn.get() else {
+                    bail!("failed to read checkpoint {}: Redis is disconnected", key);
+                };
+                let (block, gaps, unconfirmed_from): (Option<u64>, Option<String>, Option<u64>) =
+                    redis::pipe()
+                        .get(key)
+                        .get(gaps_key)
+                        .get(unconfirmed_key)
+                        .query_async(&mut conn)
+                        .await
+                        .wrap_err_with(|| format!("failed to read checkpoint {}", key))?;
+                let gaps = match gaps {
+                    Some(json) => serde_json::from_str(&json)
+                        .wrap_err_with(|| format!("invalid gap list {}", gaps_key))?,
+                    None => Vec::new(),
+                };
+                Ok(block.map(|last_block| Checkpoint {
+                    last_block,
+                    gaps,
+                    unconfirmed_from,
+                }))
+            }
+            Self::File { path } => match std::fs::read_to_string(path) {
+                Ok(contents) => {
+                    let file: CheckpointFile = serde_json::from_str(&contents)
+                        .wrap_err_with(|| format!("invalid checkpoint file {}", path.display()))?;
+                    if file.chain_id != chain_id {
+                        bail!(
+                            "checkpoint file {} belongs to chain {}, not {}",
+                            path.display(),
+                            file.chain_id,
+                            chain_id
+                        );
+                    }
+                    Ok(Some(file.checkpoint))
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => {
+                    Err(e).wrap_err_with(|| format!("failed to read checkpoint {}", path.display()))
+                }
+            },
+        }
+    }
+
+    pub async fn save(&self, chain_id: u64, checkpoint: Checkpoint) -> Result<()> {
+        match self {
+            Self::Redis {
+                conn,
+                key,
+                gaps_key,
+                unconfirmed_key,
+            } => {
+                let Some(mut conn) = conn.get() else {
+                    bail!("failed to write checkpoint {}: Redis is disconnected", key);
+                };
+                let mut pipe = redis::pipe();
+                pipe.atomic()
+                    .set(key, checkpoint.last_block)
+                    .ignore()
+                    .set(gaps_key, serde_json::to_string(&checkpoint.gaps)?)
+                    .ignore();
+                match checkpoint.unconfirmed_from {
+                    Some(block) => pipe.set(unconfirmed_key, block).ignore(),
+                    None => pipe.del(unconfirmed_key).ignore(),
+                };
+                pipe.query_async::<()>(&mut conn)
+                    .await
+                    .wrap_err_with(|| format!("failed to write checkpoint {}", key))
+            }
+            Self::File { path } => {
+                let json = serde_json::to_string(&CheckpointFile {
+                    chain_id,
+                    checkpoint,
+                })?;
+                // Write then rename so a crash never leaves a truncated file
+                let tmp = path.with_extension("tmp");
+                std::fs::write(&tmp, json)
+                    .and_then(|_| std::fs::rename(&tmp, path))
+                    .wrap_err_with(|| format!("failed to write checkpoint {}", path.display()))
+            }
+        }
+    }
+
+    /// Forget the saved progress.
+    pub async fn clear(&self) -> Result<()> {
+        match self {
+            Self::Redis {
+                conn,
+                key,
+                gaps_key,
+                unconfirmed_key,
+            } => {
+                let Some(mut conn) = conn.get() else {
+                    bail!("failed to delete checkpoint {}: Redis is disconnected", key);
+                };
+                redis::cmd("DEL")
+                    .arg(key)
+                    .arg(gaps_key)
+                    .arg(unconfirmed_key)
+                    .query_async::<()>(&mut conn)
+                    .await
+                    .wrap_err_with(|| format!("failed to delete checkpoint {}", key))
+            }
+            Self::File { path } => match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)
+                    .wrap_err_with(|| format!("failed to delete checkpoint {}", path.display())),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+/// Pick the block to resume after.
+///
+/// An explicit `start_block` always wins. Otherwise resume from the saved
+/// checkpoint, but never more than `max_catchup` blocks behind `head`. With
+/// neither, start at `head` as a fresh deployment would.
+pub fn resume_point(
+    head: u64,
+    checkpoint: Option<u64>,
+    start_block: Option<u64>,
+    max_catchup: u64,
+) -> u64 {
+    if let Some(start) = start_block {
+        return start.saturating_sub(1);
+    }
+    match checkpoint {
+        Some(saved) => saved.max(head.saturating_sub(max_catchup)).min(head),
+        None => head,
+    }
+}
+
+/// Pick the block a backfill of `from..` resumes after, given the progress
+/// an interrupted run saved. `None` means nothing is done yet and the
+/// backfill starts at `from` itself, which may be block 0.
+pub fn backfill_resume_point(from: u64, saved: Option<u64>) -> Option<u64> {
+    match saved {
+        Some(saved) if saved + 1 >= from => Some(saved),
+        _ => from.checked_sub(1),
+    }
+}
+
+/// Pick the block to scan from after `resume_after`, going back far enough
+/// to pick up events that were still waiting for confirmation when the
+/// checkpoint was saved. Only applies when resuming right at the checkpoint;
+/// after `--start-block` or skipping ahead those blocks aren't rescanned.
+pub fn rescan_point(
+    resume_after: u64,
+    saved: Option<u64>,
+    start_block: Option<u64>,
+    unconfirmed_from: Option<u64>,
+) -> u64 {
+    match unconfirmed_from {
+        Some(block) if start_block.is_none() && saved == Some(resume_after) => {
+            block.saturating_sub(1).min(resume_after)
+        }
+        _ => resume_after,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "block-monitor-checkpoint-{}-{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn start_block_wins() {
+        assert_eq!(resume_point(1_000, Some(900), Some(500), 50), 499);
+        assert_eq!(resume_point(1_000, None, Some(0), 50), 0);
+    }
+
+    #[test]
+    fn resumes_from_the_checkpoint_within_max_catchup() {
+        assert_eq!(resume_point(1_000, Some(990), None, 50), 990);
+        assert_eq!(resume_point(1_000, Some(900), None, 50), 950);
+        // A checkpoint ahead of the head (e.g. a lagging RPC) waits for it
+        assert_eq!(resume_point(1_000, Some(1_010), None, 50), 1_000);
+    }
+
+    #[test]
+    fn starts_at_head_without_a_checkpoint() {
+        assert_eq!(resume_point(1_000, None, None, 50), 1_000);
+    }
+
+    #[test]
+    fn backfill_resumes_after_saved_progress() {
+        assert_eq!(backfill_resume_point(100, None), Some(99));
+        assert_eq!(backfill_resume_point(100, Some(150)), Some(150));
+        // Progress from before the range doesn't count
+        assert_eq!(backfill_resume_point(100, Some(50)), Some(99));
+    }
+
+    #[test]
+    fn backfill_from_zero_includes_block_zero() {
+        assert_eq!(backfill_resume_point(0, None), None);
+        assert_eq!(backfill_resume_point(0, Some(0)), Some(0));
+    }
+
+    #[test]
+    fn rescans_unconfirmed_blocks_when_resuming_at_the_checkpoint() {
+        assert_eq!(rescan_point(990, Some(990), None, Some(985)), 984);
+        assert_eq!(rescan_point(990, Some(990), None, None), 990);
+        // Already covered by the normal scan
+        assert_eq!(rescan_point(990, Some(990), None, Some(995)), 990);
+    }
+
+    #[test]
+    fn no_rescan_after_skipping_ahead_or_an_explicit_start() {
+        assert_eq!(rescan_point(950, Some(900), None, Some(890)), 950);
+        assert_eq!(rescan_point(499, Some(990), Some(500), Some(985)), 499);
+        assert_eq!(rescan_point(1_000, None, None, Some(985)), 1_000);
+    }
+
+    #[tokio::test]
+    async fn file_checkpoint_round_trips() {
+        let path = temp_path("round-trip");
+        let store = CheckpointStore::file(&path);
+        store.clear().await.unwrap();
+        assert!(store.load(1).await.unwrap().is_none());
+
+        let checkpoint = Checkpoint {
+            last_block: 42,
+            ..Default::default()
+        };
+        store.save(1, checkpoint).await.unwrap();
+        assert_eq!(store.load(1).await.unwrap().unwrap().last_block, 42);
+        // Another chain's checkpoint is never resumed from
+        assert!(store.load(2).await.is_err());
+
+        store.clear().await.unwrap();
+        assert!(store.load(1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn file_checkpoint_keeps_unconfirmed_from() {
+        let path = temp_path("unconfirmed");
+        let store = CheckpointStore::file(&path);
+
+        let checkpoint = Checkpoint {
+            last_block: 42,
+            gaps: Vec::new(),
+            unconfirmed_from: Some(40),
+        };
+        store.save(1, checkpoint).await.unwrap();
+        assert_eq!(
+            store.load(1).await.unwrap().unwrap().unconfirmed_from,
+            Some(40)
+        );
+
+        let checkpoint = Checkpoint {
+            last_block: 43,
+            ..Default::default()
+        };
+        store.save(1, checkpoint).await.unwrap();
+        assert_eq!(store.load(1).await.unwrap().unwrap().unconfirmed_from, None);
+
+        store.clear().await.unwrap();
+    }
+}
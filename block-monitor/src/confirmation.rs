@@ -0,0 +1,261 @@
+use crate::{events::EventId, StreamEvent};
+use alloy::{eips::BlockNumberOrTag, providers::Provider, rpc::types::BlockTransactionsKind};
+use eyre::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr, sync::Arc};
+
+/// How deep a transfer has to be before it counts as confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmationTarget {
+    /// This many blocks below the chain head
+    Depth(u64),
+    /// The node's `safe` block
+    Safe,
+    /// The node's `finalized` block
+    Finalized,
+}
+
+impl From<ConfirmationTarget> for BlockNumberOrTag {
+    fn from(target: ConfirmationTarget) -> Self {
+        match target {
+            ConfirmationTarget::Depth(_) => BlockNumberOrTag::Latest,
+            ConfirmationTarget::Safe => BlockNumberOrTag::Safe,
+            ConfirmationTarget::Finalized => BlockNumberOrTag::Finalized,
+        }
+    }
+}
+
+impl FromStr for ConfirmationTarget {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "safe" => Ok(Self::Safe),
+            "finalized" => Ok(Self::Finalized),
+            depth => match depth.parse() {
+                Ok(depth) => Ok(Self::Depth(depth)),
+                Err(_) => bail!(
+                    "invalid confirmation target {:?} (expected a block count, safe or finalized)",
+                    s
+                ),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ConfirmationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Depth(depth) => write!(f, "{} blocks", depth),
+            Self::Safe => write!(f, "safe"),
+            Self::Finalized => write!(f, "finalized"),
+        }
+    }
+}
+
+/// When transfers get published relative to their confirmation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmationMode {
+    /// Publish once at the chain head, as `pending`
+    Head,
+    /// Publish once, as `confirmed`, after the transfer reaches the target
+    Delayed,
+    /// Publish as `pending` at the head and again as `confirmed` later
+    Dual,
+}
+
+impl FromStr for ConfirmationMode {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "head" => Ok(Self::Head),
+            "delayed" => Ok(Self::Delayed),
+            "dual" => Ok(Self::Dual),
+            _ => bail!(
+                "invalid confirmation mode {:?} (expected head, delayed or dual)",
+                s
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ConfirmationPolicy {
+    pub mode: ConfirmationMode,
+    pub target: ConfirmationTarget,
+}
+
+impl ConfirmationPolicy {
+    /// Highest block that satisfies the confirmation target, given the
+    /// current chain head.
+    pub async fn confirmed_head(&self, provider: &Arc<dyn Provider>, latest: u64) -> Result<u64> {
+        match self.target {
+            ConfirmationTarget::Depth(depth) => Ok(latest.saturating_sub(depth)),
+            ConfirmationTarget::Safe | ConfirmationTarget::Finalized => {
+                let tag = BlockNumberOrTag::from(self.target);
+                let block = provider
+                    .get_block_by_number(tag, BlockTransactionsKind::Hashes)
+                    .await?
+                    .ok_or_else(|| eyre::eyre!("node returned no {} block", self.target))?;
+                Ok(block.header.number.min(latest))
+            }
+        }
+    }
+
+    /// Status carried by the first event published for a transfer.
+    pub fn initial_status(&self) -> TransferStatus {
+        match self.mode {
+            ConfirmationMode::Head | ConfirmationMode::Dual => TransferStatus::Pending,
+            ConfirmationMode::Delayed => TransferStatus::Confirmed,
+        }
+    }
+}
+
+impl fmt::Display for ConfirmationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            ConfirmationMode::Head => write!(f, "publish at head"),
+            ConfirmationMode::Delayed => write!(f, "publish at {}", self.target),
+            ConfirmationMode::Dual => write!(f, "publish at head and again at {}", self.target),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    /// Seen at the chain head; may still be reorged out
+    Pending,
+    /// Reached the configured confirmation depth or tag
+    Confirmed,
+}
+
+impl TransferStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Confirmed => "confirmed",
+        }
+    }
+}
+
+/// Events waiting to be published as `confirmed`, keyed by block number:
+/// transfers published as `pending` in dual mode, and approval and admin
+/// events, which can't be retracted and so aren't published before then.
+#[derive(Default)]
+pub struct PendingConfirmations {
+    blocks: BTreeMap<u64, Vec<(EventId, StreamEvent)>>,
+}
+
+impl PendingConfirmations {
+    pub fn insert(&mut self, key: EventId, event: StreamEvent) {
+        self.blocks
+            .entry(event.block_number())
+            .or_default()
+            .push((key, event));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Lowest block with an event still waiting.
+    pub fn first_block(&self) -> Option<u64> {
+        self.blocks.keys().next().copied()
+    }
+
+    /// Forget everything after `fork_point`; those blocks are being replaced.
+    pub fn rewind(&mut self, fork_point: u64) {
+        self.blocks.split_off(&(fork_point + 1));
+    }
+
+    /// Take every event in blocks up to and including `block_number`.
+    pub fn confirm_up_to(&mut self, block_number: u64) -> Vec<StreamEvent> {
+        let remaining = self.blocks.split_off(&(block_number + 1));
+        let confirmed = std::mem::replace(&mut self.blocks, remaining);
+        confirmed
+            .into_values()
+            .flatten()
+            .map(|(_, event)| event)
+            .collect()
+    }
+
+    pub fn remove(&mut self, key: &EventId) {
+        for events in self.blocks.values_mut() {
+            events.retain(|(k, _)| k != key);
+        }
+        self.blocks.retain(|_, events| !events.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionData;
+
+    fn event(block: u64, log_index: u64) -> (EventId, StreamEvent) {
+        let data = TransactionData::example(block, log_index);
+        (
+            EventId::new(&data.tx_hash, log_index),
+            StreamEvent::Transfer(data),
+        )
+    }
+
+    fn positions(events: &[StreamEvent]) -> Vec<(u64, u64)> {
+        events
+            .iter()
+            .map(|event| match event {
+                StreamEvent::Transfer(data) => (data.block_number, data.log_index),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn confirms_in_block_order() {
+        let mut pending = PendingConfirmations::default();
+        for (block, log_index) in [(12, 0), (10, 3), (11, 0), (10, 1)] {
+            let (key, event) = event(block, log_index);
+            pending.insert(key, event);
+        }
+        assert_eq!(pending.first_block(), Some(10));
+
+        let confirmed = pending.confirm_up_to(11);
+        // Blocks in order, events within a block in the order they were seen
+        assert_eq!(positions(&confirmed), vec![(10, 3), (10, 1), (11, 0)]);
+        assert_eq!(pending.first_block(), Some(12));
+
+        assert!(pending.confirm_up_to(11).is_empty());
+        assert_eq!(positions(&pending.confirm_up_to(20)), vec![(12, 0)]);
+        assert!(pending.is_empty());
+        assert_eq!(pending.first_block(), None);
+    }
+
+    #[test]
+    fn rewind_drops_blocks_after_the_fork_point() {
+        let mut pending = PendingConfirmations::default();
+        for block in [10, 11, 12] {
+            let (key, event) = event(block, 0);
+            pending.insert(key, event);
+        }
+        pending.rewind(10);
+        assert_eq!(positions(&pending.confirm_up_to(20)), vec![(10, 0)]);
+    }
+
+    #[test]
+    fn remove_forgets_a_single_event() {
+        let mut pending = PendingConfirmations::default();
+        let (first, event_a) = event(10, 0);
+        let (second, event_b) = event(10, 1);
+        let (third, event_c) = event(11, 0);
+        pending.insert(first.clone(), event_a);
+        pending.insert(second, event_b);
+        pending.insert(third.clone(), event_c);
+
+        pending.remove(&first);
+        assert_eq!(pending.first_block(), Some(10));
+        pending.remove(&third);
+        assert_eq!(positions(&pending.confirm_up_to(20)), vec![(10, 1)]);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::redis_conn::RedisConnection;
+use redis::aio::MultiplexedConnection;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+const KEY_PREFIX: &str = "block-monitor:published";
+
+/// Events remembered in memory when running without Redis
+const LOCAL_CAPACITY: usize = 10_000;
+
+/// How long a claim holds before its event is delivered. A claim left by a
+/// process that died with the event still queued lapses after this.
+const CLAIM_TTL: Duration = Duration::from_secs(600);
+
+/// Redis key claiming `id`, a [publication ID](crate::StreamEvent::publication_id).
+/// game-server deduplicates by the same ID; keep the two in sync.
+fn key(id: &str) -> String {
+    format!("{}:{}", KEY_PREFIX, id)
+}
+
+/// Bounded set of recently seen IDs; the oldest are forgotten first.
+struct RecentIds {
+    capacity: usize,
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl RecentIds {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ids: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns false if `id` was already present.
+    fn insert(&mut self, id: &str) -> bool {
+        if !self.ids.insert(id.to_string()) {
+            return false;
+        }
+        self.order.push_back(id.to_string());
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    fn remove(&mut self, id: &str) {
+        if self.ids.remove(id) {
+            self.order.retain(|other| other != id);
+        }
+    }
+}
+
+/// Remembers which events have been published so restarts, retries and
+/// reorg reprocessing don't publish them twice. Backed by `SET NX` keys with
+/// a TTL when Redis is available, so the window survives restarts.
+pub struct Deduplicator {
+    redis: Option<RedisConnection>,
+    ttl: Duration,
+    local: Mutex<RecentIds>,
+}
+
+impl Deduplicator {
+    pub fn new(redis: Option<RedisConnection>, ttl: Duration) -> Self {
+        Self {
+            redis,
+            ttl,
+            local: Mutex::new(RecentIds::new(LOCAL_CAPACITY)),
+        }
+    }
+
+    /// How long a delivered event is remembered.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Claim `id` for publishing. Returns false if it was published within
+    /// the window, or is being published. A Redis error counts as a claim:
+    /// publishing twice is better than not at all. Follow up with
+    /// [`confirm`](Self::confirm) once delivered, or
+    /// [`release`](Self::release).
+    pub async fn claim(&self, id: &str) -> bool {
+        let Some(mut conn) = self.conn() else {
+            return self.local().insert(id);
+        };
+
+        match redis::cmd("SET")
+            .arg(key(id))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(self.claim_ttl().as_secs().max(1))
+            .query_async::<Option<String>>(&mut conn)
+            .await
+        {
+            Ok(claimed) => claimed.is_some(),
+            Err(e) => {
+                warn!(
+                    "Could not check whether {} was already published: {}",
+                    id, e
+                );
+                true
+            }
+        }
+    }
+
+    /// Keep the claim on a delivered event for the whole window.
+    pub async fn confirm(&self, id: &str) {
+        let Some(mut conn) = self.conn() else {
+            return;
+        };
+
+        if let Err(e) = redis::cmd("SET")
+            .arg(key(id))
+            .arg(1)
+            .arg("EX")
+            .arg(self.ttl.as_secs().max(1))
+            .query_async::<()>(&mut conn)
+            .await
+        {
+            warn!("Could not extend publish claim for {}: {}", id, e);
+        }
+    }
+
+    /// Give up a claim after delivery failed, so a later attempt isn't
+    /// mistaken for a duplicate.
+    pub async fn release(&self, id: &str) {
+        let Some(mut conn) = self.conn() else {
+            self.local().remove(id);
+            return;
+        };
+
+        if let Err(e) = redis::cmd("DEL")
+            .arg(key(id))
+            .query_async::<()>(&mut conn)
+            .await
+        {
+            warn!("Could not release publish claim for {}: {}", id, e);
+        }
+    }
+
+    /// How long a claim holds before its event is delivered.
+    fn claim_ttl(&self) -> Duration {
+        CLAIM_TTL.min(self.ttl)
+    }
+
+    /// The Redis connection, unless there's none or it's down, in which
+    /// case the in-memory window stands in.
+    fn conn(&self) -> Option<MultiplexedConnection> {
+        self.redis.as_ref().and_then(RedisConnection::get)
+    }
+
+    fn local(&self) -> std::sync::MutexGuard<'_, RecentIds> {
+        self.local.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{confirmation::TransferStatus, StreamEvent, TransactionData};
+
+    const BLOCK_HASH: &str = "0x0000000000000000000000000000000000000000000000000000000000000064";
+    const TX_HASH: &str = "0x6464646464646464646464646464646464646464646464646464646464646464";
+
+    #[test]
+    fn keys_are_prefixed_publication_ids() {
+        // game-server's dedup_key test expects the same IDs
+        let transfer = TransactionData::example(100, 3);
+        let pending = StreamEvent::Transfer(transfer.clone());
+        assert_eq!(
+            key(&pending.publication_id()),
+            format!(
+                "block-monitor:published:8453:transfer:pending:{}:{}:3",
+                BLOCK_HASH, TX_HASH
+            )
+        );
+
+        let confirmed = StreamEvent::Transfer(TransactionData {
+            status: TransferStatus::Confirmed,
+            ..transfer.clone()
+        });
+        assert_eq!(
+            confirmed.publication_id(),
+            format!("8453:transfer:confirmed:{}:{}:3", BLOCK_HASH, TX_HASH)
+        );
+        let retracted = StreamEvent::Retracted(transfer);
+        assert_eq!(
+            retracted.publication_id(),
+            format!("8453:retracted:pending:{}:{}:3", BLOCK_HASH, TX_HASH)
+        );
+    }
+
+    #[test]
+    fn claims_never_outlast_the_window() {
+        let dedup = |secs| Deduplicator::new(None, Duration::from_secs(secs));
+        assert_eq!(dedup(86400).claim_ttl(), CLAIM_TTL);
+        assert_eq!(dedup(60).claim_ttl(), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn claims_can_be_released_for_another_attempt() {
+        let dedup = Deduplicator::new(None, Duration::from_secs(60));
+        assert!(dedup.claim("a").await);
+        assert!(!dedup.claim("a").await);
+
+        dedup.release("a").await;
+        assert!(dedup.claim("a").await);
+
+        dedup.confirm("a").await;
+        assert!(!dedup.claim("a").await);
+        assert!(dedup.claim("b").await);
+    }
+
+    #[test]
+    fn only_recent_ids_are_remembered() {
+        let mut ids = RecentIds::new(2);
+        assert!(ids.insert("a"));
+        assert!(ids.insert("b"));
+        assert!(!ids.insert("a"));
+        assert!(ids.insert("c"));
+        // "a" was the oldest
+        assert!(ids.insert("a"));
+        assert!(!ids.insert("c"));
+    }
+}
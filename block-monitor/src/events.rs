@@ -0,0 +1,545 @@
+use crate::{confirmation::TransferStatus, metrics::metrics};
+use alloy::{
+    primitives::{Address, Bytes, B256, U256},
+    rpc::types::Log,
+    sol,
+    sol_types::{SolCall, SolEvent},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, sync::Mutex};
+
+sol! {
+    /// Standard ERC20 transfer
+    event Transfer(address indexed from, address indexed to, uint256 value);
+
+    /// ERC20 allowance change, also emitted by EIP-2612 `permit`
+    event Approval(address indexed owner, address indexed spender, uint256 value);
+
+    // Circle FiatToken (USDC, EURC)
+    event Blacklisted(address indexed _account);
+    event UnBlacklisted(address indexed _account);
+
+    // Tether (USDT)
+    event AddedBlackList(address _user);
+    event RemovedBlackList(address _user);
+    event DestroyedBlackFunds(address _blackListedUser, uint256 _balance);
+
+    // Both issuers
+    event Pause();
+    event Unpause();
+}
+
+/// Some older tokens index the transfer value too, so it arrives as a fourth
+/// topic with empty data. The signature hash is the same.
+mod indexed {
+    alloy::sol! {
+        event Transfer(address indexed from, address indexed to, uint256 indexed value);
+    }
+}
+
+/// The two `permit` signatures approvals are signed with.
+mod permit {
+    alloy::sol! {
+        /// EIP-2612
+        function permit(address owner, address spender, uint256 value, uint256 deadline, uint8 v, bytes32 r, bytes32 s);
+        /// DAI-style
+        function permit(address holder, address spender, uint256 nonce, uint256 expiry, bool allowed, uint8 v, bytes32 r, bytes32 s);
+    }
+}
+
+/// Batching calls a permit is commonly sent inside.
+mod multicall {
+    alloy::sol! {
+        /// OpenZeppelin `Multicall`, Uniswap V3 periphery
+        function multicall(bytes[] data);
+        /// Uniswap SwapRouter02, with a deadline
+        function multicall(uint256 deadline, bytes[] data);
+        /// Uniswap SwapRouter02, with the expected parent block hash
+        function multicall(bytes32 previousBlockhash, bytes[] data);
+
+        struct Call {
+            address target;
+            bytes callData;
+        }
+        struct Call3 {
+            address target;
+            bool allowFailure;
+            bytes callData;
+        }
+        /// Multicall3
+        function aggregate(Call[] calls);
+        function tryAggregate(bool requireSuccess, Call[] calls);
+        function aggregate3(Call3[] calls);
+    }
+}
+
+/// How deep multicalls nested in multicalls are searched
+const MAX_MULTICALL_DEPTH: usize = 3;
+
+/// Topic hashes for every event decoded here.
+pub fn signatures(approvals: bool) -> Vec<B256> {
+    let mut signatures = vec![
+        Transfer::SIGNATURE_HASH,
+        Blacklisted::SIGNATURE_HASH,
+        UnBlacklisted::SIGNATURE_HASH,
+        AddedBlackList::SIGNATURE_HASH,
+        RemovedBlackList::SIGNATURE_HASH,
+        DestroyedBlackFunds::SIGNATURE_HASH,
+        Pause::SIGNATURE_HASH,
+        Unpause::SIGNATURE_HASH,
+    ];
+    if approvals {
+        signatures.push(Approval::SIGNATURE_HASH);
+    }
+    signatures
+}
+
+/// Canonical identity of a published event: the transaction and the log's
+/// position in the block, rendered as `<tx_hash>:<log_index>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EventId {
+    pub tx_hash: String,
+    pub log_index: u64,
+}
+
+impl EventId {
+    pub fn new(tx_hash: &str, log_index: u64) -> Self {
+        Self {
+            tx_hash: tx_hash.to_string(),
+            log_index,
+        }
+    }
+}
+
+impl fmt::Display for EventId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.tx_hash, self.log_index)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalData {
+    pub stablecoin: String,
+    pub owner: String,
+    pub spender: String,
+    pub amount: String,
+    /// Set through a signed EIP-2612 permit rather than by the owner
+    pub via_permit: bool,
+    pub block_number: u64,
+    pub block_hash: String,
+    /// Block timestamp in Unix seconds
+    pub timestamp: u64,
+    pub tx_hash: String,
+    pub tx_index: u64,
+    pub log_index: u64,
+    pub chain_id: u64,
+    pub status: TransferStatus,
+}
+
+/// Issuer actions on a token: freezing accounts, seizing frozen funds and
+/// pausing the contract.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AdminAction {
+    Blacklisted { account: String },
+    UnBlacklisted { account: String },
+    AddedBlackList { account: String },
+    RemovedBlackList { account: String },
+    DestroyedBlackFunds { account: String, amount: String },
+    Pause,
+    Unpause,
+}
+
+impl AdminAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Blacklisted { .. } => "blacklisted",
+            Self::UnBlacklisted { .. } => "un_blacklisted",
+            Self::AddedBlackList { .. } => "added_black_list",
+            Self::RemovedBlackList { .. } => "removed_black_list",
+            Self::DestroyedBlackFunds { .. } => "destroyed_black_funds",
+            Self::Pause => "pause",
+            Self::Unpause => "unpause",
+        }
+    }
+
+    pub fn account(&self) -> Option<&str> {
+        match self {
+            Self::Blacklisted { account }
+            | Self::UnBlacklisted { account }
+            | Self::AddedBlackList { account }
+            | Self::RemovedBlackList { account }
+            | Self::DestroyedBlackFunds { account, .. } => Some(account),
+            Self::Pause | Self::Unpause => None,
+        }
+    }
+
+    pub fn amount(&self) -> Option<&str> {
+        match self {
+            Self::DestroyedBlackFunds { amount, .. } => Some(amount),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminEventData {
+    pub stablecoin: String,
+    #[serde(flatten)]
+    pub action: AdminAction,
+    pub block_number: u64,
+    pub block_hash: String,
+    /// Block timestamp in Unix seconds
+    pub timestamp: u64,
+    pub tx_hash: String,
+    pub tx_index: u64,
+    pub log_index: u64,
+    pub chain_id: u64,
+    pub status: TransferStatus,
+}
+
+/// A token log decoded into a typed event, before it's tied to a token and
+/// block.
+pub enum Decoded {
+    Transfer {
+        from: Address,
+        to: Address,
+        value: U256,
+    },
+    Approval(Approval),
+    Admin(AdminAction),
+}
+
+/// Why a log couldn't be decoded.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// Anonymous event with no signature topic
+    MissingSignature,
+    /// Signature we don't decode; the log filter shouldn't let these through
+    UnknownEvent(B256),
+    /// Known signature, but not a topic layout any token we know of uses
+    TopicCount { event: &'static str, found: usize },
+    /// Topics or data don't ABI-decode as the event
+    Abi {
+        event: &'static str,
+        source: alloy::sol_types::Error,
+    },
+}
+
+impl DecodeError {
+    /// Short label for metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::MissingSignature => "missing_signature",
+            Self::UnknownEvent(_) => "unknown_event",
+            Self::TopicCount { .. } => "topic_count",
+            Self::Abi { .. } => "abi",
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSignature => write!(f, "log has no topics"),
+            Self::UnknownEvent(topic0) => write!(f, "unknown event signature {}", topic0),
+            Self::TopicCount { event, found } => {
+                write!(f, "{} log with unexpected topic count {}", event, found)
+            }
+            Self::Abi { event, source } => write!(f, "malformed {} log: {}", event, source),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Abi { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn decode_as<E: SolEvent>(log: &Log) -> Result<E, DecodeError> {
+    E::decode_log_data(log.data(), true).map_err(|source| DecodeError::Abi {
+        event: E::SIGNATURE,
+        source,
+    })
+}
+
+/// Decode any event we subscribe to. `format_amount` turns raw token units
+/// into the token's decimal representation.
+pub fn decode(log: &Log, format_amount: impl Fn(U256) -> String) -> Result<Decoded, DecodeError> {
+    let topic0 = *log.topic0().ok_or(DecodeError::MissingSignature)?;
+    let account = |address: Address| format!("{:?}", address);
+
+    let decoded = if topic0 == Transfer::SIGNATURE_HASH {
+        match log.topics().len() {
+            3 => {
+                let event = decode_as::<Transfer>(log)?;
+                Decoded::Transfer {
+                    from: event.from,
+                    to: event.to,
+                    value: event.value,
+                }
+            }
+            4 => {
+                let event = decode_as::<indexed::Transfer>(log)?;
+                Decoded::Transfer {
+                    from: event.from,
+                    to: event.to,
+                    value: event.value,
+                }
+            }
+            found => {
+                return Err(DecodeError::TopicCount {
+                    event: "Transfer",
+                    found,
+                })
+            }
+        }
+    } else if topic0 == Approval::SIGNATURE_HASH {
+        Decoded::Approval(decode_as::<Approval>(log)?)
+    } else if topic0 == Blacklisted::SIGNATURE_HASH {
+        let event = decode_as::<Blacklisted>(log)?;
+        Decoded::Admin(AdminAction::Blacklisted {
+            account: account(event._account),
+        })
+    } else if topic0 == UnBlacklisted::SIGNATURE_HASH {
+        let event = decode_as::<UnBlacklisted>(log)?;
+        Decoded::Admin(AdminAction::UnBlacklisted {
+            account: account(event._account),
+        })
+    } else if topic0 == AddedBlackList::SIGNATURE_HASH {
+        let event = decode_as::<AddedBlackList>(log)?;
+        Decoded::Admin(AdminAction::AddedBlackList {
+            account: account(event._user),
+        })
+    } else if topic0 == RemovedBlackList::SIGNATURE_HASH {
+        let event = decode_as::<RemovedBlackList>(log)?;
+        Decoded::Admin(AdminAction::RemovedBlackList {
+            account: account(event._user),
+        })
+    } else if topic0 == DestroyedBlackFunds::SIGNATURE_HASH {
+        let event = decode_as::<DestroyedBlackFunds>(log)?;
+        Decoded::Admin(AdminAction::DestroyedBlackFunds {
+            account: account(event._blackListedUser),
+            amount: format_amount(event._balance),
+        })
+    } else if topic0 == Pause::SIGNATURE_HASH {
+        decode_as::<Pause>(log)?;
+        Decoded::Admin(AdminAction::Pause)
+    } else if topic0 == Unpause::SIGNATURE_HASH {
+        decode_as::<Unpause>(log)?;
+        Decoded::Admin(AdminAction::Unpause)
+    } else {
+        return Err(DecodeError::UnknownEvent(topic0));
+    };
+    Ok(decoded)
+}
+
+/// Logs that failed to decode on one chain, by reason. Counted for the
+/// health endpoint and in `decode_failures_total`.
+pub struct DecodeFailures {
+    chain: String,
+    counts: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl DecodeFailures {
+    pub fn new(chain: &str) -> Self {
+        Self {
+            chain: chain.to_string(),
+            counts: Mutex::default(),
+        }
+    }
+
+    pub fn record(&self, error: &DecodeError) {
+        metrics()
+            .decode_failures
+            .with_label_values(&[&self.chain, error.reason()])
+            .inc();
+        *self
+            .counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(error.reason())
+            .or_default() += 1;
+    }
+
+    pub fn counts(&self) -> BTreeMap<&'static str, u64> {
+        self.counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// Whether an approval was made with a signed permit: the transaction was
+/// sent by someone other than the owner and calls `permit`, directly or
+/// inside a known multicall.
+pub fn is_permit(owner: Address, sender: Address, input: &Bytes) -> bool {
+    sender != owner && calls_permit(input, MAX_MULTICALL_DEPTH)
+}
+
+/// Whether `input` is a call to `permit`, or a multicall with one among its
+/// calls.
+fn calls_permit(input: &[u8], depth: usize) -> bool {
+    let Some(selector) = input.get(..4) else {
+        return false;
+    };
+    if selector == permit::permit_0Call::SELECTOR || selector == permit::permit_1Call::SELECTOR {
+        return true;
+    }
+    if depth == 0 {
+        return false;
+    }
+
+    use multicall::*;
+    let calls: Vec<Bytes> = match <[u8; 4]>::try_from(selector).unwrap_or_default() {
+        multicall_0Call::SELECTOR => multicall_0Call::abi_decode(input, false)
+            .map(|call| call.data)
+            .unwrap_or_default(),
+        multicall_1Call::SELECTOR => multicall_1Call::abi_decode(input, false)
+            .map(|call| call.data)
+            .unwrap_or_default(),
+        multicall_2Call::SELECTOR => multicall_2Call::abi_decode(input, false)
+            .map(|call| call.data)
+            .unwrap_or_default(),
+        aggregateCall::SELECTOR => aggregateCall::abi_decode(input, false)
+            .map(|call| call.calls.into_iter().map(|call| call.callData).collect())
+            .unwrap_or_default(),
+        tryAggregateCall::SELECTOR => tryAggregateCall::abi_decode(input, false)
+            .map(|call| call.calls.into_iter().map(|call| call.callData).collect())
+            .unwrap_or_default(),
+        aggregate3Call::SELECTOR => aggregate3Call::abi_decode(input, false)
+            .map(|call| call.calls.into_iter().map(|call| call.callData).collect())
+            .unwrap_or_default(),
+        _ => return false,
+    };
+    calls.iter().any(|call| calls_permit(call, depth - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{address, LogData};
+
+    const FROM: Address = address!("1111111111111111111111111111111111111111");
+    const TO: Address = address!("2222222222222222222222222222222222222222");
+
+    fn log(topics: Vec<B256>, data: Vec<u8>) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: Address::ZERO,
+                data: LogData::new_unchecked(topics, Bytes::from(data)),
+            },
+            ..Default::default()
+        }
+    }
+
+    fn decode_transfer(log: &Log) -> Result<(Address, Address, U256), DecodeError> {
+        match decode(log, |amount| amount.to_string())? {
+            Decoded::Transfer { from, to, value } => Ok((from, to, value)),
+            _ => panic!("decoded as something other than a transfer"),
+        }
+    }
+
+    fn transfer_topics() -> Vec<B256> {
+        vec![Transfer::SIGNATURE_HASH, FROM.into_word(), TO.into_word()]
+    }
+
+    #[test]
+    fn transfer_with_value_in_data() {
+        let value = U256::from(1_500_000u64);
+        let log = log(transfer_topics(), value.to_be_bytes_vec());
+        assert_eq!(decode_transfer(&log).unwrap(), (FROM, TO, value));
+    }
+
+    #[test]
+    fn transfer_with_indexed_value() {
+        let value = U256::from(42u64);
+        let mut topics = transfer_topics();
+        topics.push(B256::from(value));
+        let log = log(topics, Vec::new());
+        assert_eq!(decode_transfer(&log).unwrap(), (FROM, TO, value));
+    }
+
+    #[test]
+    fn transfer_with_wrong_topic_count() {
+        let log = log(
+            vec![Transfer::SIGNATURE_HASH, FROM.into_word()],
+            U256::from(1u64).to_be_bytes_vec(),
+        );
+        let error = decode_transfer(&log).unwrap_err();
+        assert!(matches!(
+            error,
+            DecodeError::TopicCount {
+                event: "Transfer",
+                found: 2
+            }
+        ));
+        assert_eq!(error.reason(), "topic_count");
+    }
+
+    #[test]
+    fn unknown_signature() {
+        let mut topics = transfer_topics();
+        topics[0] = B256::repeat_byte(0xab);
+        let log = log(topics, U256::from(1u64).to_be_bytes_vec());
+        let error = decode_transfer(&log).unwrap_err();
+        assert!(
+            matches!(error, DecodeError::UnknownEvent(topic0) if topic0 == B256::repeat_byte(0xab))
+        );
+        assert_eq!(error.reason(), "unknown_event");
+    }
+
+    #[test]
+    fn no_topics() {
+        let error = decode_transfer(&log(Vec::new(), Vec::new())).unwrap_err();
+        assert!(matches!(error, DecodeError::MissingSignature));
+    }
+
+    #[test]
+    fn transfer_with_short_data() {
+        let log = log(transfer_topics(), vec![0; 16]);
+        let error = decode_transfer(&log).unwrap_err();
+        assert!(matches!(
+            error,
+            DecodeError::Abi {
+                event: "Transfer(address,address,uint256)",
+                ..
+            }
+        ));
+        assert_eq!(error.reason(), "abi");
+    }
+
+    #[test]
+    fn permit_selectors() {
+        assert_eq!(permit::permit_0Call::SELECTOR, [0xd5, 0x05, 0xac, 0xcf]);
+        assert_eq!(permit::permit_1Call::SELECTOR, [0x8f, 0xcb, 0xaf, 0x0c]);
+    }
+
+    #[test]
+    fn permits_are_found_inside_multicalls() {
+        let permit = permit::permit_0Call {
+            owner: FROM,
+            spender: TO,
+            value: U256::from(1u64),
+            deadline: U256::MAX,
+            v: 27,
+            r: B256::ZERO,
+            s: B256::ZERO,
+        }
+        .abi_encode();
+        let multicall = multicall::multicall_0Call {
+            data: vec![Bytes::from(vec![0; 4]), Bytes::from(permit.clone())],
+        }
+        .abi_encode();
+
+        assert!(is_permit(FROM, TO, &Bytes::from(permit.clone())));
+        assert!(is_permit(FROM, TO, &Bytes::from(multicall)));
+        // The owner sending it themselves is a plain approval
+        assert!(!is_permit(FROM, FROM, &Bytes::from(permit)));
+        assert!(!is_permit(FROM, TO, &Bytes::from(vec![0xd5, 0x05])));
+    }
+}
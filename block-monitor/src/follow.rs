@@ -0,0 +1,248 @@
+use eyre::{bail, Result};
+use futures_util::{future::BoxFuture, stream::BoxStream, StreamExt};
+use std::time::Duration;
+use tokio::{sync::watch, time};
+use tracing::{info, warn};
+
+// How long the polling fallback runs before retrying the WebSocket subscription
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(60);
+
+// A subscription that delivers no heads for this long is treated as dropped
+pub const HEAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where new chain heads come from, and what's done with them.
+pub trait HeadSource: Send + Sync {
+    /// Subscribe to `newHeads`, yielding block numbers.
+    fn subscribe_heads(&self) -> BoxFuture<'_, Result<BoxStream<'static, u64>>>;
+    /// The current head, over plain RPC.
+    fn current_head(&self) -> BoxFuture<'_, Result<u64>>;
+    fn process_head(&self, head: u64) -> BoxFuture<'_, ()>;
+    /// Poll for new blocks until `limit` elapses or the monitor stops.
+    fn poll_for(&self, limit: Duration) -> BoxFuture<'_, ()>;
+}
+
+/// Follows the chain head over a `newHeads` subscription, falling back to
+/// polling while the subscription is down and retrying it periodically.
+pub struct HeadFollower {
+    head_timeout: Duration,
+    resubscribe_interval: Duration,
+}
+
+impl HeadFollower {
+    pub fn new() -> Self {
+        Self {
+            head_timeout: HEAD_TIMEOUT,
+            resubscribe_interval: RESUBSCRIBE_INTERVAL,
+        }
+    }
+
+    /// Follow the head until `stop` is set.
+    pub async fn run(&self, source: &dyn HeadSource, stop: watch::Receiver<bool>) {
+        while !*stop.borrow() {
+            match self.follow(source, stop.clone()).await {
+                Ok(()) if *stop.borrow() => break,
+                Ok(()) => warn!("newHeads subscription ended, falling back to polling"),
+                Err(e) => warn!(
+                    "newHeads subscription failed: {}. Falling back to polling",
+                    e
+                ),
+            }
+
+            // Keep processing blocks while we wait to resubscribe
+            source.poll_for(self.resubscribe_interval).await;
+            if !*stop.borrow() {
+                info!("Retrying newHeads subscription");
+            }
+        }
+    }
+
+    /// Process heads as the subscription delivers them. Returns when the
+    /// subscription drops or goes quiet for too long.
+    async fn follow(&self, source: &dyn HeadSource, mut stop: watch::Receiver<bool>) -> Result<()> {
+        let mut heads = source.subscribe_heads().await?;
+
+        // Pick up anything that landed before the subscription started
+        let current_block = source.current_head().await?;
+        source.process_head(current_block).await;
+
+        loop {
+            let head = tokio::select! {
+                head = time::timeout(self.head_timeout, heads.next()) => head,
+                _ = stop.wait_for(|stop| *stop) => return Ok(()),
+            };
+            match head {
+                Ok(Some(head)) => source.process_head(head).await,
+                Ok(None) => return Ok(()),
+                Err(_) => bail!("no new heads received in {:?}", self.head_timeout),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+    use std::{collections::VecDeque, sync::Mutex};
+
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        Subscribe,
+        Head(u64),
+        Poll,
+    }
+
+    /// Hands out the scripted subscriptions in turn, then stops the follower.
+    struct FakeSource {
+        subscriptions: Mutex<VecDeque<Result<Vec<u64>>>>,
+        /// Keep each subscription open after its heads instead of ending it
+        hold_open: bool,
+        /// Stop once this head has been processed
+        stop_at: Option<u64>,
+        current: u64,
+        calls: Mutex<Vec<Call>>,
+        stop: watch::Sender<bool>,
+    }
+
+    impl FakeSource {
+        fn new(subscriptions: Vec<Result<Vec<u64>>>) -> Self {
+            Self {
+                subscriptions: Mutex::new(subscriptions.into()),
+                hold_open: false,
+                stop_at: None,
+                current: 10,
+                calls: Mutex::new(Vec::new()),
+                stop: watch::Sender::new(false),
+            }
+        }
+
+        fn record(&self, call: Call) {
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    impl HeadSource for FakeSource {
+        fn subscribe_heads(&self) -> BoxFuture<'_, Result<BoxStream<'static, u64>>> {
+            self.record(Call::Subscribe);
+            let next = self.subscriptions.lock().unwrap().pop_front();
+            let hold_open = self.hold_open;
+            Box::pin(async move {
+                let Some(heads) = next else {
+                    self.stop.send_replace(true);
+                    bail!("out of subscriptions");
+                };
+                let heads = stream::iter(heads?);
+                Ok(if hold_open {
+                    heads.chain(stream::pending()).boxed()
+                } else {
+                    heads.boxed()
+                })
+            })
+        }
+
+        fn current_head(&self) -> BoxFuture<'_, Result<u64>> {
+            Box::pin(async move { Ok(self.current) })
+        }
+
+        fn process_head(&self, head: u64) -> BoxFuture<'_, ()> {
+            self.record(Call::Head(head));
+            if self.stop_at == Some(head) {
+                self.stop.send_replace(true);
+            }
+            Box::pin(async {})
+        }
+
+        fn poll_for(&self, _limit: Duration) -> BoxFuture<'_, ()> {
+            // Polling returns straight away once stopped
+            if !*self.stop.borrow() {
+                self.record(Call::Poll);
+            }
+            Box::pin(async {})
+        }
+    }
+
+    fn follower() -> HeadFollower {
+        HeadFollower {
+            head_timeout: Duration::from_millis(50),
+            resubscribe_interval: Duration::from_millis(10),
+        }
+    }
+
+    async fn run(source: &FakeSource) -> Vec<Call> {
+        time::timeout(
+            Duration::from_secs(5),
+            follower().run(source, source.stop.subscribe()),
+        )
+        .await
+        .expect("follower didn't stop");
+        std::mem::take(&mut *source.calls.lock().unwrap())
+    }
+
+    #[tokio::test]
+    async fn heads_are_processed_until_the_subscription_ends() {
+        let source = FakeSource::new(vec![Ok(vec![11, 12])]);
+
+        assert_eq!(
+            run(&source).await,
+            [
+                Call::Subscribe,
+                Call::Head(10),
+                Call::Head(11),
+                Call::Head(12),
+                Call::Poll,
+                Call::Subscribe,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_subscription_falls_back_to_polling_and_retries() {
+        let source = FakeSource::new(vec![Err(eyre::eyre!("refused")), Ok(vec![11])]);
+
+        assert_eq!(
+            run(&source).await,
+            [
+                Call::Subscribe,
+                Call::Poll,
+                Call::Subscribe,
+                Call::Head(10),
+                Call::Head(11),
+                Call::Poll,
+                Call::Subscribe,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn quiet_subscription_is_dropped() {
+        let source = FakeSource {
+            hold_open: true,
+            ..FakeSource::new(vec![Ok(vec![11])])
+        };
+
+        assert_eq!(
+            run(&source).await,
+            [
+                Call::Subscribe,
+                Call::Head(10),
+                Call::Head(11),
+                Call::Poll,
+                Call::Subscribe,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stopping_ends_the_subscription_without_polling() {
+        let source = FakeSource {
+            hold_open: true,
+            stop_at: Some(11),
+            ..FakeSource::new(vec![Ok(vec![11])])
+        };
+
+        assert_eq!(
+            run(&source).await,
+            [Call::Subscribe, Call::Head(10), Call::Head(11)]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use alloy::primitives::{I256, U256};
+use eyre::{bail, Result};
+use std::str::FromStr;
+
+/// Largest exponent for which 10^n still fits in a U256; bounds both token
+/// decimals and the places amounts are formatted to
+pub const MAX_DECIMALS: u8 = 77;
+
+/// How many decimal places formatted amounts show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmountStyle {
+    /// Every decimal the token has, e.g. `1.500000` for a 6-decimal token
+    #[default]
+    Full,
+    /// Trailing zeros dropped, e.g. `1.5`, and `2` for whole amounts
+    Trimmed,
+    /// Rounded half up to exactly this many places, e.g. `1.50` at 2
+    Fixed(u8),
+}
+
+impl FromStr for AmountStyle {
+    type Err = eyre::Report;
+
+    /// `full`, `trimmed` or a number of places.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "full" => Ok(Self::Full),
+            "trimmed" | "trim" => Ok(Self::Trimmed),
+            places => match places.parse::<u8>() {
+                Ok(places) if places <= MAX_DECIMALS => Ok(Self::Fixed(places)),
+                Ok(_) => bail!(
+                    "invalid amount format {:?}; at most {} decimal places are supported",
+                    s,
+                    MAX_DECIMALS
+                ),
+                Err(_) => bail!(
+                    "invalid amount format {:?}; expected full, trimmed or a number of decimal places",
+                    s
+                ),
+            },
+        }
+    }
+}
+
+/// Format `amount` raw units of a token with `decimals` decimals. Every
+/// amount in published events goes through here.
+pub fn format_units(amount: U256, decimals: u8, style: AmountStyle) -> String {
+    // Zeros appended past the token's own precision
+    let (amount, places, padding) = match style {
+        AmountStyle::Fixed(places) if places < decimals => {
+            // Round half up, then drop the extra digits
+            let dropped = U256::from(10).pow(U256::from(decimals - places));
+            let mut rounded = amount / dropped;
+            if amount % dropped >= dropped / U256::from(2) {
+                rounded += U256::from(1);
+            }
+            (rounded, places, 0)
+        }
+        AmountStyle::Fixed(places) => (amount, decimals, places - decimals),
+        AmountStyle::Full | AmountStyle::Trimmed => (amount, decimals, 0),
+    };
+
+    if places == 0 && padding == 0 {
+        return amount.to_string();
+    }
+
+    let divisor = U256::from(10).pow(U256::from(places));
+    let whole = amount / divisor;
+    let mut fraction = match places {
+        0 => String::new(),
+        places => format!("{:0width$}", amount % divisor, width = usize::from(places)),
+    };
+    fraction.extend(std::iter::repeat_n('0', usize::from(padding)));
+
+    match style {
+        AmountStyle::Trimmed => match fraction.trim_end_matches('0') {
+            "" => whole.to_string(),
+            fraction => format!("{}.{}", whole, fraction),
+        },
+        AmountStyle::Full | AmountStyle::Fixed(_) => format!("{}.{}", whole, fraction),
+    }
+}
+
+/// [`format_units`] for a signed amount, with a leading `-` when negative.
+pub fn format_signed(amount: I256, decimals: u8, style: AmountStyle) -> String {
+    let formatted = format_units(amount.unsigned_abs(), decimals, style);
+    if amount.is_negative() {
+        format!("-{}", formatted)
+    } else {
+        formatted
+    }
+}
+
+/// Nearest `f64` to the token amount. Only for display and rough
+/// comparisons; the raw units are the exact value.
+pub fn to_f64(amount: U256, decimals: u8) -> f64 {
+    // Parsing the exact decimal string keeps f64 rounding to a single step
+    format_units(amount, decimals, AmountStyle::Full)
+        .parse()
+        .unwrap_or(f64::INFINITY)
+}
+
+/// Serde helpers for a `U256` written as a plain decimal string rather than
+/// alloy's default hex.
+pub mod u256_decimal {
+    use alloy::primitives::U256;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        U256::from_str_radix(&value, 10).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn units(amount: u128) -> U256 {
+        U256::from(amount)
+    }
+
+    #[test]
+    fn fixed_rounds_half_up() {
+        assert_eq!(
+            format_units(units(1_234_567), 6, AmountStyle::Fixed(2)),
+            "1.23"
+        );
+        assert_eq!(
+            format_units(units(1_235_000), 6, AmountStyle::Fixed(2)),
+            "1.24"
+        );
+        assert_eq!(
+            format_units(units(1_234_999), 6, AmountStyle::Fixed(2)),
+            "1.23"
+        );
+    }
+
+    #[test]
+    fn fixed_rounding_carries_into_the_whole_part() {
+        assert_eq!(
+            format_units(units(995_000), 6, AmountStyle::Fixed(2)),
+            "1.00"
+        );
+        assert_eq!(
+            format_units(units(9_999_999), 6, AmountStyle::Fixed(2)),
+            "10.00"
+        );
+        assert_eq!(
+            format_units(units(1_500_000), 6, AmountStyle::Fixed(0)),
+            "2"
+        );
+        assert_eq!(
+            format_units(units(1_499_999), 6, AmountStyle::Fixed(0)),
+            "1"
+        );
+    }
+
+    #[test]
+    fn fixed_pads_past_the_token_precision() {
+        assert_eq!(format_units(units(150), 2, AmountStyle::Fixed(4)), "1.5000");
+        assert_eq!(format_units(units(7), 0, AmountStyle::Fixed(2)), "7.00");
+        assert_eq!(
+            format_units(units(1_500_000), 6, AmountStyle::Fixed(6)),
+            "1.500000"
+        );
+    }
+
+    #[test]
+    fn trimmed_drops_trailing_zeros() {
+        assert_eq!(
+            format_units(units(1_500_000), 6, AmountStyle::Trimmed),
+            "1.5"
+        );
+        assert_eq!(format_units(units(2_000_000), 6, AmountStyle::Trimmed), "2");
+        assert_eq!(format_units(units(1), 6, AmountStyle::Trimmed), "0.000001");
+        assert_eq!(format_units(U256::ZERO, 6, AmountStyle::Trimmed), "0");
+    }
+
+    #[test]
+    fn full_shows_every_decimal() {
+        assert_eq!(
+            format_units(units(1_500_000), 6, AmountStyle::Full),
+            "1.500000"
+        );
+        assert_eq!(
+            format_units(units(1), 18, AmountStyle::Full),
+            "0.000000000000000001"
+        );
+        assert_eq!(format_units(U256::ZERO, 6, AmountStyle::Full), "0.000000");
+    }
+
+    #[test]
+    fn zero_decimals() {
+        assert_eq!(format_units(units(42), 0, AmountStyle::Full), "42");
+        assert_eq!(format_units(units(42), 0, AmountStyle::Trimmed), "42");
+        assert_eq!(format_units(units(42), 0, AmountStyle::Fixed(0)), "42");
+    }
+
+    #[test]
+    fn amounts_near_u256_max() {
+        let max = U256::MAX;
+        assert_eq!(
+            format_units(max, 0, AmountStyle::Full),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935"
+        );
+        assert_eq!(
+            format_units(max, 18, AmountStyle::Full),
+            "115792089237316195423570985008687907853269984665640564039457.584007913129639935"
+        );
+        assert_eq!(
+            format_units(max, 18, AmountStyle::Fixed(2)),
+            "115792089237316195423570985008687907853269984665640564039457.58"
+        );
+        // Rounds up without overflowing
+        assert_eq!(
+            format_units(max, 1, AmountStyle::Fixed(0)),
+            "11579208923731619542357098500868790785326998466564056403945758400791312963994"
+        );
+        assert_eq!(
+            format_units(max, 0, AmountStyle::Fixed(2)),
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935.00"
+        );
+        assert_eq!(
+            format_units(max, MAX_DECIMALS, AmountStyle::Trimmed),
+            "1.15792089237316195423570985008687907853269984665640564039457584007913129639935"
+        );
+    }
+
+    #[test]
+    fn signed_amounts() {
+        let amount = I256::try_from(-1_500_000i64).unwrap();
+        assert_eq!(format_signed(amount, 6, AmountStyle::Full), "-1.500000");
+        assert_eq!(format_signed(amount, 6, AmountStyle::Trimmed), "-1.5");
+        assert_eq!(format_signed(amount, 6, AmountStyle::Fixed(0)), "-2");
+        assert_eq!(
+            format_signed(I256::try_from(250i64).unwrap(), 2, AmountStyle::Full),
+            "2.50"
+        );
+        assert_eq!(
+            format_signed(I256::MIN, 0, AmountStyle::Full),
+            "-57896044618658097711785492504343953926634992332820282019728792003956564819968"
+        );
+    }
+
+    #[test]
+    fn to_f64_converts_exactly_representable_amounts() {
+        assert_eq!(to_f64(units(1_500_000), 6), 1.5);
+        assert_eq!(to_f64(U256::ZERO, 18), 0.0);
+        assert_eq!(to_f64(units(1_000_000_000_000_000_000), 18), 1.0);
+        assert!((to_f64(U256::MAX, 18) - 1.157920892373162e59).abs() < 1e45);
+    }
+
+    #[test]
+    fn u256_decimal_round_trips() {
+        #[derive(serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+        struct Amount {
+            #[serde(with = "u256_decimal")]
+            raw: U256,
+        }
+
+        for raw in [U256::ZERO, units(1_500_000), U256::MAX] {
+            let json = serde_json::to_string(&Amount { raw }).unwrap();
+            assert_eq!(json, format!("{{\"raw\":\"{}\"}}", raw));
+            assert_eq!(
+                serde_json::from_str::<Amount>(&json).unwrap(),
+                Amount { raw }
+            );
+        }
+        assert!(serde_json::from_str::<Amount>(r#"{"raw":"0x10"}"#).is_err());
+        assert!(serde_json::from_str::<Amount>(r#"{"raw":"-1"}"#).is_err());
+    }
+
+    #[test]
+    fn amount_style_from_str() {
+        assert_eq!("full".parse::<AmountStyle>().unwrap(), AmountStyle::Full);
+        assert_eq!(
+            " Trimmed ".parse::<AmountStyle>().unwrap(),
+            AmountStyle::Trimmed
+        );
+        assert_eq!("trim".parse::<AmountStyle>().unwrap(), AmountStyle::Trimmed);
+        assert_eq!("2".parse::<AmountStyle>().unwrap(), AmountStyle::Fixed(2));
+        assert_eq!("77".parse::<AmountStyle>().unwrap(), AmountStyle::Fixed(77));
+        assert!("78".parse::<AmountStyle>().is_err());
+        assert!("100".parse::<AmountStyle>().is_err());
+        assert!("256".parse::<AmountStyle>().is_err());
+        assert!("-1".parse::<AmountStyle>().is_err());
+        assert!("rounded".parse::<AmountStyle>().is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+const BASE_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// A block that failed to process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gap {
+    pub block: u64,
+    pub attempts: u32,
+    pub last_error: String,
+    /// Out of quick retries; the block is missing from the published data
+    /// and only retried every [`MAX_BACKOFF`]
+    pub abandoned: bool,
+    #[serde(skip, default = "Instant::now")]
+    next_attempt: Instant,
+}
+
+/// Failed blocks waiting to be retried with exponential backoff. Blocks
+/// that run out of attempts are reported as abandoned but still retried,
+/// every [`MAX_BACKOFF`] and after a restart.
+pub struct RetryQueue {
+    gaps: BTreeMap<u64, Gap>,
+    max_attempts: u32,
+}
+
+impl RetryQueue {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            gaps: BTreeMap::new(),
+            max_attempts: max_attempts.max(1),
+        }
+    }
+
+    /// Restore the gaps saved with the checkpoint, due for a retry straight
+    /// away. Catching up after a long outage may skip past them, so they
+    /// can't be left to the re-scan.
+    pub fn restore(&mut self, gaps: Vec<Gap>) {
+        for gap in gaps {
+            self.gaps.insert(gap.block, gap);
+        }
+    }
+
+    /// Record a failed attempt at `block`. Returns true if the block has just
+    /// run out of quick retries.
+    pub fn fail(&mut self, block: u64, error: String) -> bool {
+        let max_attempts = self.max_attempts;
+        let gap = self.gaps.entry(block).or_insert_with(|| Gap {
+            block,
+            attempts: 0,
+            last_error: String::new(),
+            abandoned: false,
+            next_attempt: Instant::now(),
+        });
+        gap.attempts += 1;
+        gap.last_error = error;
+        gap.next_attempt = Instant::now() + backoff(gap.attempts);
+        gap.abandoned = gap.attempts >= max_attempts;
+        gap.attempts == max_attempts
+    }
+
+    pub fn resolve(&mut self, block: u64) {
+        self.gaps.remove(&block);
+    }
+
+    /// Blocks whose backoff has elapsed, in block order.
+    pub fn due(&self) -> Vec<u64> {
+        let now = Instant::now();
+        self.gaps
+            .values()
+            .filter(|gap| gap.next_attempt <= now)
+            .map(|gap| gap.block)
+            .collect()
+    }
+
+    /// Lowest block still on quick retries; the checkpoint can't move past
+    /// it. Abandoned blocks are saved with the checkpoint instead, so they
+    /// survive a restart without holding it back.
+    pub fn first_retrying(&self) -> Option<u64> {
+        self.gaps
+            .values()
+            .find(|gap| !gap.abandoned)
+            .map(|gap| gap.block)
+    }
+
+    /// Block the checkpoint can safely record once `scanned` is done: just
+    /// before the first block still on quick retries, if any.
+    pub fn checkpoint_block(&self, scanned: u64) -> u64 {
+        match self.first_retrying() {
+            Some(block) => block.saturating_sub(1).min(scanned),
+            None => scanned,
+        }
+    }
+
+    pub fn gaps(&self) -> Vec<Gap> {
+        self.gaps.values().cloned().collect()
+    }
+}
+
+fn backoff(attempts: u32) -> Duration {
+    BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(1), Duration::from_secs(2));
+        assert_eq!(backoff(2), Duration::from_secs(4));
+        assert_eq!(backoff(3), Duration::from_secs(8));
+        assert_eq!(backoff(8), Duration::from_secs(256));
+        assert_eq!(backoff(9), MAX_BACKOFF);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn failed_blocks_wait_for_their_backoff() {
+        let mut retries = RetryQueue::new(3);
+        retries.fail(10, "boom".into());
+        assert!(retries.due().is_empty());
+
+        retries.gaps.get_mut(&10).unwrap().next_attempt = Instant::now();
+        assert_eq!(retries.due(), vec![10]);
+    }
+
+    #[test]
+    fn blocks_are_abandoned_after_max_attempts() {
+        let mut retries = RetryQueue::new(3);
+        assert!(!retries.fail(10, "one".into()));
+        assert!(!retries.fail(10, "two".into()));
+        assert!(retries.fail(10, "three".into()));
+        // Only reported once, but still retried
+        assert!(!retries.fail(10, "four".into()));
+
+        let gaps = retries.gaps();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].attempts, 4);
+        assert_eq!(gaps[0].last_error, "four");
+        assert!(gaps[0].abandoned);
+        assert_eq!(retries.first_retrying(), None);
+    }
+
+    #[test]
+    fn checkpoint_stops_before_a_block_still_retrying() {
+        let mut retries = RetryQueue::new(2);
+        assert_eq!(retries.checkpoint_block(100), 100);
+
+        retries.fail(50, "boom".into());
+        retries.fail(60, "boom".into());
+        assert_eq!(retries.checkpoint_block(100), 49);
+        // Never ahead of what was actually scanned
+        assert_eq!(retries.checkpoint_block(40), 40);
+
+        // Abandoned blocks no longer hold it back
+        retries.fail(50, "boom".into());
+        assert_eq!(retries.checkpoint_block(100), 59);
+
+        retries.resolve(60);
+        assert_eq!(retries.checkpoint_block(100), 100);
+        assert_eq!(retries.gaps().len(), 1);
+    }
+
+    #[test]
+    fn restored_gaps_are_due_straight_away() {
+        let saved = Gap {
+            block: 7,
+            attempts: 5,
+            last_error: "boom".into(),
+            abandoned: true,
+            next_attempt: Instant::now(),
+        };
+        let json = serde_json::to_string(&[saved]).unwrap();
+
+        let mut retries = RetryQueue::new(5);
+        retries.restore(serde_json::from_str(&json).unwrap());
+        assert_eq!(retries.due(), vec![7]);
+        assert_eq!(retries.checkpoint_block(100), 100);
+    }
+}
@@ -0,0 +1,48 @@
+use alloy::primitives::B256;
+use std::collections::{HashMap, VecDeque};
+
+/// The parts of a block header attached to published events.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockHeader {
+    pub number: u64,
+    pub hash: B256,
+    pub parent_hash: B256,
+    /// Unix seconds
+    pub timestamp: u64,
+}
+
+/// Recently fetched headers keyed by block hash, so every log in a block
+/// shares one header lookup. Keying by hash rather than number means a reorg
+/// can never serve a stale header.
+pub struct HeaderCache {
+    capacity: usize,
+    headers: HashMap<B256, BlockHeader>,
+    /// Insertion order, oldest first
+    order: VecDeque<B256>,
+}
+
+impl HeaderCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            headers: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn get(&self, hash: &B256) -> Option<BlockHeader> {
+        self.headers.get(hash).copied()
+    }
+
+    pub fn insert(&mut self, header: BlockHeader) {
+        if self.headers.insert(header.hash, header).is_some() {
+            return;
+        }
+        self.order.push_back(header.hash);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.headers.remove(&oldest);
+            }
+        }
+    }
+}
@@ -1,97 +1,526 @@
+mod catchup;
+mod chains;
+mod checkpoint;
+mod config;
+mod confirmation;
+mod dedup;
+mod events;
+mod follow;
+mod format;
+mod gaps;
+mod headers;
+mod metadata;
+mod metrics;
+mod outbox;
+mod redis_conn;
+mod registry;
+mod reorg;
+mod rpc_pool;
+mod server;
+mod sinks;
+mod supply;
+
 use alloy::{
-    primitives::{address, Address, FixedBytes, U256},
-    providers::{Provider, ProviderBuilder},
-    rpc::types::Filter,
+    consensus::Transaction as _,
+    primitives::{Address, Bytes, B256, I256, U256},
+    providers::{Provider, ProviderBuilder, WsConnect},
+    rpc::types::{BlockTransactionsKind, Filter, Log},
+};
+use catchup::{progress, RangeFetcher};
+use checkpoint::{Checkpoint, CheckpointStore};
+use clap::{Args, Parser, Subcommand};
+use config::{ChainSettings, Config, Secret};
+use confirmation::{ConfirmationMode, ConfirmationPolicy, PendingConfirmations, TransferStatus};
+use dedup::Deduplicator;
+use events::{AdminEventData, ApprovalData, DecodeFailures, Decoded, EventId};
+use eyre::{Result, WrapErr};
+use follow::{HeadFollower, HeadSource};
+use format::AmountStyle;
+use futures_util::{
+    future::{self, BoxFuture},
+    stream::BoxStream,
+    StreamExt,
 };
-use eyre::Result;
-use futures_util::{SinkExt, StreamExt};
+use gaps::RetryQueue;
+use headers::{BlockHeader, HeaderCache};
+use metadata::MetadataVerifier;
+use metrics::metrics;
 use redis::aio::MultiplexedConnection;
 use redis::Client as RedisClient;
+use redis_conn::RedisConnection;
+use registry::{SharedRegistry, StablecoinInfo, TokenRegistry};
+use reorg::ReorgTracker;
+use rpc_pool::RpcPool;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, env, net::SocketAddr, sync::Arc, time::Duration};
+use sinks::{BackfillSinks, Sinks};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use supply::{EventKind, SupplyChange, SupplyTracker, SupplyUpdate};
 use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::{broadcast, RwLock},
+    sync::{broadcast, watch, Mutex, RwLock},
     time,
 };
-use tokio_tungstenite::{accept_async, tungstenite::Message};
-use tracing::{error, info, warn};
-
-// Known stablecoin addresses on Base network (Chain ID: 8453)
-// Source: Base official token list and Base docs
-// Verified on BaseScan: https://basescan.org/tokens
-const USDC_ADDRESS: Address = address!("833589fCD6eDb6E08f4c7C32D4f71b54bdA02913"); // USDC on Base
-const USDT_ADDRESS: Address = address!("fde4C96c8593536E31F229EA8f37b2ADa2699bb2"); // USDT on Base
-const DAI_ADDRESS: Address = address!("50c5725949A6F0c72E6C4a641F24049A917DB0Cb"); // DAI on Base
-
-// ERC20 Transfer event signature
-const TRANSFER_EVENT_SIGNATURE: FixedBytes<32> = FixedBytes::new([
-    0xdd, 0xf2, 0x52, 0xad, 0x1b, 0xe2, 0xc8, 0x9b, 0x69, 0xc2, 0xb0, 0x68, 0xfc, 0x37, 0x8d, 0xaa,
-    0x95, 0x2b, 0xa7, 0xf1, 0x63, 0xc4, 0xa1, 0x16, 0x28, 0xf5, 0x5a, 0x4d, 0xf5, 0x23, 0xb3, 0xef,
-]);
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct TransactionData {
     pub stablecoin: String,
+    /// Token contract address
+    pub token: String,
+    /// Amount formatted per `AMOUNT_FORMAT`
     pub amount: String,
     pub from: String,
     pub to: String,
     pub block_number: u64,
+    pub block_hash: String,
+    /// Block timestamp in Unix seconds
+    pub timestamp: u64,
     pub tx_hash: String,
+    /// Position of the transaction in the block
+    pub tx_index: u64,
+    /// Position of the log in the block
+    pub log_index: u64,
+    /// EIP-155 chain ID the transfer happened on
+    pub chain_id: u64,
+    pub event_kind: EventKind,
+    pub status: TransferStatus,
+    /// Exact amount in token units, as a decimal string
+    #[serde(with = "format::u256_decimal")]
+    pub raw_amount: U256,
+    pub decimals: u8,
+    /// Approximate USD value, for tokens with a known USD rate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usd_value: Option<f64>,
+}
+
+impl TransactionData {
+    fn id(&self) -> EventId {
+        EventId::new(&self.tx_hash, self.log_index)
+    }
+
+    /// A pending 1 USDC transfer at `log_index` in `block_number`, for tests.
+    #[cfg(test)]
+    fn example(block_number: u64, log_index: u64) -> Self {
+        Self {
+            stablecoin: "USDC".to_string(),
+            token: format!("{:?}", Address::repeat_byte(0x11)),
+            amount: "1.000000".to_string(),
+            from: format!("{:?}", Address::repeat_byte(0x22)),
+            to: format!("{:?}", Address::repeat_byte(0x33)),
+            block_number,
+            block_hash: format!("{:#x}", B256::with_last_byte(block_number as u8)),
+            timestamp: 1_700_000_000 + block_number,
+            tx_hash: format!("{:#x}", B256::repeat_byte(block_number as u8)),
+            tx_index: 0,
+            log_index,
+            chain_id: 8453,
+            event_kind: EventKind::Transfer,
+            status: TransferStatus::Pending,
+            raw_amount: U256::from(1_000_000u64),
+            decimals: 6,
+            usd_value: None,
+        }
+    }
+}
+
+/// Everything published on the stream and to WebSocket clients. Serialized
+/// with a `type` tag alongside the event's fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    Transfer(TransactionData),
+    /// A previously published transfer that is no longer on the canonical
+    /// chain
+    Retracted(TransactionData),
+    /// An allowance change, directly or through a permit
+    Approval(ApprovalData),
+    /// Blacklisting, seizure or pausing by the token issuer
+    Admin(AdminEventData),
+    /// A mint or burn's change to its token's net supply; Redis only
+    Supply(SupplyUpdate),
+}
+
+impl StreamEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Transfer(_) => "transfer",
+            Self::Retracted(_) => "retracted",
+            Self::Approval(_) => "approval",
+            Self::Admin(_) => "admin",
+            Self::Supply(_) => "supply",
+        }
+    }
+
+    fn stablecoin(&self) -> &str {
+        match self {
+            Self::Transfer(tx_data) | Self::Retracted(tx_data) => &tx_data.stablecoin,
+            Self::Approval(approval) => &approval.stablecoin,
+            Self::Admin(admin) => &admin.stablecoin,
+            Self::Supply(update) => &update.stablecoin,
+        }
+    }
+
+    fn block_number(&self) -> u64 {
+        match self {
+            Self::Transfer(tx_data) | Self::Retracted(tx_data) => tx_data.block_number,
+            Self::Approval(approval) => approval.block_number,
+            Self::Admin(admin) => admin.block_number,
+            Self::Supply(update) => update.block_number,
+        }
+    }
+
+    fn set_status(&mut self, status: TransferStatus) {
+        match self {
+            Self::Transfer(tx_data) | Self::Retracted(tx_data) => tx_data.status = status,
+            Self::Approval(approval) => approval.status = status,
+            Self::Admin(admin) => admin.status = status,
+            // Published as the transfer it follows is, never held back
+            Self::Supply(_) => {}
+        }
+    }
+
+    /// Deterministic ID for one publication of an event. The same transfer
+    /// is published again as confirmed or retracted, and again if a reorg
+    /// moves it to another block, so those are part of the ID.
+    fn publication_id(&self) -> String {
+        if let Self::Supply(update) = self {
+            return format!(
+                "{}:supply:{}:{}:{}",
+                update.chain_id,
+                if update.retracted {
+                    "retracted"
+                } else {
+                    "applied"
+                },
+                update.block_hash,
+                EventId::new(&update.tx_hash, update.log_index)
+            );
+        }
+        let (chain_id, status, block_hash, id) = match self {
+            Self::Transfer(tx_data) | Self::Retracted(tx_data) => (
+                tx_data.chain_id,
+                tx_data.status,
+                &tx_data.block_hash,
+                tx_data.id(),
+            ),
+            Self::Approval(approval) => (
+                approval.chain_id,
+                approval.status,
+                &approval.block_hash,
+                EventId::new(&approval.tx_hash, approval.log_index),
+            ),
+            Self::Admin(admin) => (
+                admin.chain_id,
+                admin.status,
+                &admin.block_hash,
+                EventId::new(&admin.tx_hash, admin.log_index),
+            ),
+            Self::Supply(_) => unreachable!("handled above"),
+        };
+        format!(
+            "{}:{}:{}:{}:{}",
+            chain_id,
+            self.kind(),
+            status.as_str(),
+            block_hash,
+            id
+        )
+    }
+
+    /// Flat fields written next to the JSON `data` on the Redis stream.
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        match self {
+            Self::Transfer(tx_data) | Self::Retracted(tx_data) => {
+                let mut fields = vec![
+                    ("stablecoin", tx_data.stablecoin.clone()),
+                    ("token", tx_data.token.clone()),
+                    ("amount", tx_data.amount.clone()),
+                    ("raw_amount", tx_data.raw_amount.to_string()),
+                    ("decimals", tx_data.decimals.to_string()),
+                    ("from", tx_data.from.clone()),
+                    ("to", tx_data.to.clone()),
+                    ("block", tx_data.block_number.to_string()),
+                    ("block_hash", tx_data.block_hash.clone()),
+                    ("timestamp", tx_data.timestamp.to_string()),
+                    ("tx_hash", tx_data.tx_hash.clone()),
+                    ("tx_index", tx_data.tx_index.to_string()),
+                    ("log_index", tx_data.log_index.to_string()),
+                    ("event_id", tx_data.id().to_string()),
+                    ("chain_id", tx_data.chain_id.to_string()),
+                    ("event_kind", tx_data.event_kind.as_str().to_string()),
+                    ("status", tx_data.status.as_str().to_string()),
+                ];
+                if let Some(usd_value) = tx_data.usd_value {
+                    fields.push(("usd_value", usd_value.to_string()));
+                }
+                fields
+            }
+            Self::Approval(approval) => vec![
+                ("stablecoin", approval.stablecoin.clone()),
+                ("owner", approval.owner.clone()),
+                ("spender", approval.spender.clone()),
+                ("amount", approval.amount.clone()),
+                ("via_permit", approval.via_permit.to_string()),
+                ("block", approval.block_number.to_string()),
+                ("block_hash", approval.block_hash.clone()),
+                ("timestamp", approval.timestamp.to_string()),
+                ("tx_hash", approval.tx_hash.clone()),
+                ("tx_index", approval.tx_index.to_string()),
+                ("log_index", approval.log_index.to_string()),
+                (
+                    "event_id",
+                    EventId::new(&approval.tx_hash, approval.log_index).to_string(),
+                ),
+                ("chain_id", approval.chain_id.to_string()),
+                ("status", approval.status.as_str().to_string()),
+            ],
+            Self::Admin(admin) => {
+                let mut fields = vec![
+                    ("stablecoin", admin.stablecoin.clone()),
+                    ("action", admin.action.as_str().to_string()),
+                    ("block", admin.block_number.to_string()),
+                    ("block_hash", admin.block_hash.clone()),
+                    ("timestamp", admin.timestamp.to_string()),
+                    ("tx_hash", admin.tx_hash.clone()),
+                    ("tx_index", admin.tx_index.to_string()),
+                    ("log_index", admin.log_index.to_string()),
+                    (
+                        "event_id",
+                        EventId::new(&admin.tx_hash, admin.log_index).to_string(),
+                    ),
+                    ("chain_id", admin.chain_id.to_string()),
+                    ("status", admin.status.as_str().to_string()),
+                ];
+                if let Some(account) = admin.action.account() {
+                    fields.push(("account", account.to_string()));
+                }
+                if let Some(amount) = admin.action.amount() {
+                    fields.push(("amount", amount.to_string()));
+                }
+                fields
+            }
+            Self::Supply(update) => vec![
+                ("chain_id", update.chain_id.to_string()),
+                ("stablecoin", update.stablecoin.clone()),
+                ("token", update.token.clone()),
+                ("event_kind", update.event_kind.as_str().to_string()),
+                ("amount", update.amount.clone()),
+                ("net_change", update.net_change.clone()),
+                ("block", update.block_number.to_string()),
+                ("block_hash", update.block_hash.clone()),
+                ("tx_hash", update.tx_hash.clone()),
+                ("log_index", update.log_index.to_string()),
+                ("retracted", update.retracted.to_string()),
+            ],
+        }
+    }
 }
 
-#[derive(Clone)]
-struct StablecoinInfo {
-    name: &'static str,
-    decimals: u8,
+// Hash holding the latest net supply totals
+const SUPPLY_TOTALS_KEY: &str = "stablecoin:supply:totals";
+
+// Per-event flags recording which mints and burns those totals include
+const SUPPLY_COUNTED_KEY: &str = "stablecoin:supply:counted";
+
+// Block headers kept for stamping events with their block's timestamp
+const HEADER_CACHE_SIZE: usize = 256;
+
+/// Command-line flags. Everything else is configured through the
+/// environment or the config file; flags take precedence over both.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Monitors stablecoin transfers and publishes them to Redis"
+)]
+struct Cli {
+    /// TOML file with settings named like the environment variables
+    #[arg(long, global = true, env = "CONFIG_FILE")]
+    config: Option<PathBuf>,
+
+    /// Chains to monitor, comma-separated; overrides CHAINS
+    #[arg(long, global = true)]
+    chains: Option<String>,
+
+    /// WebSocket, health and metrics port; overrides PORT
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Process from this block instead of resuming from the saved checkpoint
+    #[arg(long, env = "START_BLOCK")]
+    start_block: Option<u64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+impl Cli {
+    /// Settings given as flags, by the variable they override.
+    fn overrides(&self) -> HashMap<String, String> {
+        let mut overrides = HashMap::new();
+        if let Some(chains) = &self.chains {
+            overrides.insert("CHAINS".to_string(), chains.clone());
+        }
+        if let Some(port) = self.port {
+            overrides.insert("PORT".to_string(), port.to_string());
+        }
+        overrides
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Publish the events of a historical block range, then exit
+    Backfill(BackfillArgs),
+}
+
+#[derive(Args, Debug)]
+struct BackfillArgs {
+    /// First block to process
+    #[arg(long)]
+    from: u64,
+    /// Last block to process, inclusive; must be confirmed
+    #[arg(long)]
+    to: u64,
+    /// Sinks to publish to, comma-separated: redis, stdout, file, webhook
+    #[arg(long)]
+    sink: String,
+    /// Chain to backfill when CHAINS lists several
+    #[arg(long)]
+    chain: Option<String>,
+    /// Where progress is saved so an interrupted backfill can resume;
+    /// defaults to backfill-<chain>-<from>-<to>.json. The Redis outbox and
+    /// file sink output are named after it, e.g. backfill-<chain>-<from>-<to>.events.ndjson
+    #[arg(long)]
+    progress_file: Option<PathBuf>,
+}
+
+/// What a monitor is built for.
+enum RunMode {
+    /// Follow the chain head, resuming from the checkpoint
+    Follow { start_block: Option<u64> },
+    /// Publish `from..=to` to the given sinks, resuming from a progress file
+    Backfill {
+        from: u64,
+        to: u64,
+        sinks: String,
+        progress_file: PathBuf,
+    },
 }
 
 struct StablecoinMonitor {
+    /// Name from `CHAINS`, e.g. `base`
+    chain: String,
+    rpc: RpcPool,
+    /// Failover provider over every endpoint in `rpc`
     provider: Arc<dyn Provider>,
-    stablecoins: HashMap<Address, StablecoinInfo>,
+    ws_rpc_url: Option<Secret>,
+    chain_id: u64,
+    registry: SharedRegistry,
+    metadata: Arc<Mutex<MetadataVerifier>>,
+    /// Highest block with nothing below it left to retry; this is what
+    /// gets checkpointed
     last_block: Arc<RwLock<u64>>,
-    tx_broadcaster: broadcast::Sender<TransactionData>,
-    redis_conn: Option<MultiplexedConnection>,
+    /// Highest block the monitor has attempted
+    scanned_block: RwLock<u64>,
+    /// Block this run started after; `None` for a backfill that starts at
+    /// block 0 with nothing done yet
+    resume_after: Option<u64>,
+    /// Block the monitor is working towards: the head, or the newest
+    /// confirmed block in delayed mode
+    target_block: RwLock<u64>,
+    /// When the monitoring loop last made progress
+    heartbeat: RwLock<Instant>,
+    /// Readiness fails once `target_block` is further ahead than this
+    max_lag_blocks: u64,
+    /// Liveness fails once the loop has gone this long without a heartbeat
+    heartbeat_timeout: Duration,
+    /// Set to stop at the next block boundary
+    stop: watch::Sender<bool>,
+    retries: Mutex<RetryQueue>,
+    reorg: Mutex<ReorgTracker>,
+    headers: Mutex<HeaderCache>,
+    range_fetcher: RangeFetcher,
+    /// Include `Approval` events (and permit detection) in the log filter
+    decode_approvals: bool,
+    decode_failures: DecodeFailures,
+    amount_style: AmountStyle,
+    /// Blocks nearest the head that are always processed one at a time
+    catchup_tail: u64,
+    confirmations: ConfirmationPolicy,
+    pending: Mutex<PendingConfirmations>,
+    supply: Mutex<SupplyTracker>,
+    sinks: Sinks,
+    redis_conn: Option<RedisConnection>,
+    dedup: Arc<Deduplicator>,
+    checkpoint: CheckpointStore,
+}
+
+/// New heads pushed over `WS_RPC_URL`, with the RPC pool for everything else.
+impl HeadSource for StablecoinMonitor {
+    fn subscribe_heads(&self) -> BoxFuture<'_, Result<BoxStream<'static, u64>>> {
+        Box::pin(async move {
+            let Some(ws_url) = &self.ws_rpc_url else {
+                eyre::bail!("no WS_RPC_URL configured");
+            };
+            let ws_provider = ProviderBuilder::new()
+                .on_ws(WsConnect::new(ws_url.expose()))
+                .await?;
+            let heads = ws_provider.subscribe_blocks().await?.into_stream();
+            info!("Subscribed to newHeads at {}", ws_url);
+
+            // The stream holds on to the provider; dropping it would close
+            // the connection
+            Ok(heads
+                .map(move |header| {
+                    let _ = &ws_provider;
+                    header.number
+                })
+                .boxed())
+        })
+    }
+
+    fn current_head(&self) -> BoxFuture<'_, Result<u64>> {
+        Box::pin(self.rpc.block_number())
+    }
+
+    fn process_head(&self, head: u64) -> BoxFuture<'_, ()> {
+        Box::pin(self.process_new_head(head))
+    }
+
+    fn poll_for(&self, limit: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(self.poll_blocks(Some(limit)))
+    }
 }
 
 impl StablecoinMonitor {
     async fn connect_to_redis_with_retry(
-        redis_url: &str,
+        client: &RedisClient,
         max_retries: u32,
     ) -> Option<MultiplexedConnection> {
         let mut retry_count = 0;
         let mut delay = Duration::from_secs(1);
 
         loop {
-            match RedisClient::open(redis_url) {
-                Ok(client) => match client.get_multiplexed_tokio_connection().await {
-                    Ok(conn) => {
-                        info!(
-                            "Connected to Redis successfully after {} retries",
-                            retry_count
-                        );
-                        return Some(conn);
-                    }
-                    Err(e) => {
-                        if retry_count >= max_retries {
-                            warn!("Failed to connect to Redis after {} retries: {}. Running without Redis.", max_retries, e);
-                            return None;
-                        }
-                        warn!(
-                            "Redis connection attempt {} failed: {}. Retrying in {:?}...",
-                            retry_count + 1,
-                            e,
-                            delay
-                        );
-                    }
-                },
+            match RedisConnection::connect(client).await {
+                Ok(conn) => {
+                    info!(
+                        "Connected to Redis successfully after {} retries",
+                        retry_count
+                    );
+                    return Some(conn);
+                }
                 Err(e) => {
                     if retry_count >= max_retries {
-                        warn!("Failed to create Redis client after {} retries: {}. Running without Redis.", max_retries, e);
+                        warn!("Failed to connect to Redis after {} retries: {}. Running without Redis until it's back.", max_retries, e);
                         return None;
                     }
                     warn!(
-                        "Redis client creation attempt {} failed: {}. Retrying in {:?}...",
+                        "Redis connection attempt {} failed: {}. Retrying in {:?}...",
                         retry_count + 1,
                         e,
                         delay
@@ -106,78 +535,333 @@ impl StablecoinMonitor {
     }
 
     async fn new(
-        rpc_url: String,
-        tx_broadcaster: broadcast::Sender<TransactionData>,
+        settings: &ChainSettings,
+        mode: &RunMode,
+        tx_broadcaster: broadcast::Sender<StreamEvent>,
     ) -> Result<Self> {
-        // Create provider
-        let provider = ProviderBuilder::new().on_http(rpc_url.parse()?).boxed();
-
-        // Initialize stablecoin map with Base network stablecoins
-        // These addresses are specific to Base network (not Ethereum mainnet)
-        let mut stablecoins = HashMap::new();
-        stablecoins.insert(
-            USDC_ADDRESS,
-            StablecoinInfo {
-                name: "USDC",
-                decimals: 6, // USDC uses 6 decimals on Base
-            },
-        );
-        stablecoins.insert(
-            USDT_ADDRESS,
-            StablecoinInfo {
-                name: "USDT",
-                decimals: 6, // USDT uses 6 decimals on Base
-            },
+        let chain = &settings.chain;
+        let start_block = match mode {
+            RunMode::Follow { start_block } => start_block.or(settings.start_block),
+            RunMode::Backfill { .. } => None,
+        };
+
+        // Create a provider that fails over between the configured endpoints,
+        // optionally cross-checking the two best of them
+        let rpc_urls: Vec<String> = settings
+            .rpc_urls
+            .iter()
+            .map(|url| url.expose().to_string())
+            .collect();
+        let rpc = RpcPool::new(
+            &rpc_urls,
+            settings.rpc_quorum,
+            settings.rpc_quorum_tolerance,
+        )?;
+        let provider = rpc.provider();
+        info!(
+            "RPC endpoints: {}{}",
+            rpc.names().join(", "),
+            if rpc.quorum_enabled() {
+                " (quorum mode)"
+            } else {
+                ""
+            }
         );
-        stablecoins.insert(
-            DAI_ADDRESS,
-            StablecoinInfo {
-                name: "DAI",
-                decimals: 18, // DAI uses 18 decimals on Base
-            },
+
+        // Load the tokens registered for the chain these RPC endpoints serve
+        let chain_id = rpc.chain_id().await?;
+        if let Some(expected) = chain.expected_chain_id.filter(|id| *id != chain_id) {
+            eyre::bail!(
+                "RPC endpoints for {} serve chain {}, expected {}",
+                chain.name,
+                chain_id,
+                expected
+            );
+        }
+
+        // Token registry file (TOML or JSON); reloaded on SIGHUP
+        let mut registry = TokenRegistry::load(&settings.token_registry, chain_id)?;
+
+        // Check configured decimals against the token contracts before any
+        // amount gets formatted with them
+        let mut metadata = MetadataVerifier::new(
+            provider.clone(),
+            &settings.token_metadata_cache,
+            settings.token_metadata_strict,
         );
+        metadata.verify(&mut registry).await?;
+        registry.log_summary();
+
+        // Decide when transfers are published relative to confirmation
+        let mut confirmations = settings.confirmations;
+
+        // A backfill only covers history that can no longer be reorged, so
+        // everything it publishes is confirmed
+        if let RunMode::Backfill { to, .. } = mode {
+            let head = provider.get_block_number().await?;
+            let confirmed_head = confirmations.confirmed_head(&provider, head).await?;
+            if *to > confirmed_head {
+                eyre::bail!(
+                    "--to {} is past the confirmed head {} ({})",
+                    to,
+                    confirmed_head,
+                    confirmations.target
+                );
+            }
+            confirmations.mode = ConfirmationMode::Delayed;
+        }
 
-        // Get current block number
-        let current_block = provider.get_block_number().await?;
+        // Get current block number. In delayed mode nothing above the
+        // confirmed head is processed, so that's where a fresh start begins.
+        let mut current_block = provider.get_block_number().await?;
+        if confirmations.mode == ConfirmationMode::Delayed {
+            current_block = confirmations
+                .confirmed_head(&provider, current_block)
+                .await?;
+        }
 
-        // Connect to Redis with connection manager for automatic reconnection
-        let redis_conn = if let Ok(redis_url) = env::var("REDIS_URL") {
-            Self::connect_to_redis_with_retry(&redis_url, 3).await
-        } else {
-            info!("REDIS_URL not set, running without Redis");
-            None
+        // Connect to Redis, reconnecting in the background whenever it's
+        // lost. A backfill only connects for its Redis sink, keeping its
+        // dedup window, supply totals and progress out of Redis so it can't
+        // disturb a running monitor.
+        let wants_redis = match mode {
+            RunMode::Follow { .. } => true,
+            RunMode::Backfill { sinks: names, .. } => sinks::parse_names(names)?.contains(&"redis"),
+        };
+        let redis = match &settings.redis_url {
+            Some(redis_url) if wants_redis => {
+                let client = RedisClient::open(redis_url.expose()).wrap_err("invalid REDIS_URL")?;
+                let conn = Self::connect_to_redis_with_retry(&client, 3).await;
+                Some(RedisConnection::start(client, conn))
+            }
+            Some(_) => None,
+            None => {
+                info!("REDIS_URL not set, running without Redis");
+                None
+            }
+        };
+        let redis_conn = match mode {
+            RunMode::Follow { .. } => redis.clone(),
+            RunMode::Backfill { .. } => None,
         };
 
-        Ok(Self {
-            provider: Arc::new(provider),
-            stablecoins,
-            last_block: Arc::new(RwLock::new(current_block)),
+        // Pick up net supply totals where the previous run left off, or
+        // once Redis is reachable
+        let mut supply = SupplyTracker::default();
+        if let Some(conn) = redis_conn.as_ref().and_then(RedisConnection::get) {
+            if let Some(stored) = Self::load_supply_totals(&conn, chain_id, &registry).await {
+                supply.load(stored);
+            }
+        }
+
+        // Where published events go
+        let backfill = match mode {
+            RunMode::Follow { .. } => None,
+            RunMode::Backfill {
+                sinks,
+                progress_file,
+                ..
+            } => Some(BackfillSinks {
+                names: sinks,
+                progress_file,
+            }),
+        };
+        let sinks = Sinks::new(
+            &chain.name,
+            &settings.sinks,
+            backfill,
+            redis.as_ref(),
             tx_broadcaster,
+        )
+        .await?;
+
+        // How long published event IDs are remembered; covers restarts and
+        // catch-up replays
+        let dedup = Arc::new(Deduplicator::new(redis_conn.clone(), settings.dedup_ttl));
+
+        // Prefer an explicit checkpoint file, then Redis, then a local file
+        // Redis only if it was reachable at startup, as the saved progress
+        // has to be read now
+        let connected = redis_conn.as_ref().filter(|conn| conn.get().is_some());
+        let checkpoint = match (mode, &settings.checkpoint_file, connected) {
+            (RunMode::Backfill { progress_file, .. }, _, _) => {
+                CheckpointStore::file(progress_file.clone())
+            }
+            (_, Some(path), _) => CheckpointStore::file(path.clone()),
+            (_, None, Some(conn)) => CheckpointStore::redis(conn.clone(), chain_id),
+            (_, None, None) => CheckpointStore::file(format!("checkpoint-{}.json", chain_id)),
+        };
+        info!("Checkpoint store: {}", checkpoint.describe());
+
+        // Don't replay an unbounded backlog after a long outage
+        let max_catchup = settings.max_catchup_blocks;
+
+        let saved = match checkpoint.load(chain_id).await {
+            Ok(saved) => saved,
+            Err(e) => {
+                warn!("{:#}. Starting from the current head.", e);
+                None
+            }
+        };
+        let (saved, saved_gaps, unconfirmed_from) = match saved {
+            Some(checkpoint) => (
+                Some(checkpoint.last_block),
+                checkpoint.gaps,
+                checkpoint.unconfirmed_from,
+            ),
+            None => (None, Vec::new(), None),
+        };
+        let resume_after = match mode {
+            RunMode::Backfill { from, .. } => {
+                let resume_after = checkpoint::backfill_resume_point(*from, saved);
+                if let (Some(_), Some(block)) = (saved, resume_after) {
+                    info!("Resuming backfill after block {}", block);
+                }
+                resume_after
+            }
+            RunMode::Follow { .. } => {
+                let resume_after =
+                    checkpoint::resume_point(current_block, saved, start_block, max_catchup);
+                match (start_block, saved) {
+                    (Some(start), _) => info!("Starting from block {} (--start-block)", start),
+                    (None, Some(saved)) if resume_after > saved => warn!(
+                        "Checkpoint {} is {} blocks behind head {}; skipping to block {} (MAX_CATCHUP_BLOCKS={})",
+                        saved,
+                        current_block.saturating_sub(saved),
+                        current_block,
+                        resume_after + 1,
+                        max_catchup
+                    ),
+                    (None, Some(saved)) => info!(
+                        "Resuming after checkpoint block {} ({} blocks to catch up)",
+                        saved,
+                        current_block.saturating_sub(saved)
+                    ),
+                    (None, None) => info!("No checkpoint found, starting at head {}", current_block),
+                }
+                // Events held for confirmation didn't survive the restart;
+                // scan their blocks again so they're picked up. What was
+                // already published is deduplicated.
+                let rescan_after =
+                    checkpoint::rescan_point(resume_after, saved, start_block, unconfirmed_from);
+                if rescan_after < resume_after {
+                    info!(
+                        "Scanning again from block {} for events still waiting for confirmation",
+                        rescan_after + 1
+                    );
+                }
+                Some(rescan_after)
+            }
+        };
+
+        // Failed blocks are retried with backoff this many times before
+        // being reported as a gap, and every 5 minutes after that
+        let mut retries = RetryQueue::new(settings.max_block_retries);
+        match mode {
+            RunMode::Follow { .. } => {
+                retries.restore(saved_gaps);
+                let gaps = retries.gaps();
+                if !gaps.is_empty() {
+                    warn!(
+                        "{} blocks failed in a previous run and will be retried: {:?}",
+                        gaps.len(),
+                        gaps.iter().map(|gap| gap.block).collect::<Vec<_>>()
+                    );
+                }
+            }
+            // Give blocks an interrupted backfill couldn't process another go
+            RunMode::Backfill { .. } => {
+                for gap in saved_gaps {
+                    retries.fail(gap.block, gap.last_error);
+                }
+            }
+        }
+
+        // Range query tuning for catching up after downtime
+        let range_fetcher =
+            RangeFetcher::new(settings.catchup_chunk_size, settings.catchup_concurrency);
+
+        Ok(Self {
+            chain: chain.name.clone(),
+            rpc,
+            provider,
+            ws_rpc_url: settings.ws_rpc_url.clone(),
+            chain_id,
+            registry: Arc::new(RwLock::new(registry)),
+            metadata: Arc::new(Mutex::new(metadata)),
+            last_block: Arc::new(RwLock::new(resume_after.unwrap_or(0))),
+            scanned_block: RwLock::new(resume_after.unwrap_or(0)),
+            target_block: RwLock::new(resume_after.unwrap_or(0)),
+            resume_after,
+            heartbeat: RwLock::new(Instant::now()),
+            max_lag_blocks: settings.health_max_lag_blocks,
+            heartbeat_timeout: settings.health_heartbeat,
+            stop: watch::Sender::new(false),
+            retries: Mutex::new(retries),
+            reorg: Mutex::new(ReorgTracker::new(settings.reorg_depth)),
+            headers: Mutex::new(HeaderCache::new(HEADER_CACHE_SIZE)),
+            range_fetcher,
+            decode_approvals: settings.decode_approvals,
+            decode_failures: DecodeFailures::new(&chain.name),
+            amount_style: settings.amount_format,
+            catchup_tail: settings.reorg_depth as u64,
+            confirmations,
+            pending: Mutex::new(PendingConfirmations::default()),
+            supply: Mutex::new(supply),
+            sinks,
             redis_conn,
+            dedup,
+            checkpoint,
         })
     }
 
+    /// Follow the chain until [`stop`](Self::stop) is called.
     async fn start_monitoring(&self) {
+        match self.ws_rpc_url {
+            Some(_) => HeadFollower::new().run(self, self.stop.subscribe()).await,
+            None => self.poll_blocks(None).await,
+        }
+        info!("Stopped at block {}", *self.last_block.read().await);
+    }
+
+    /// Finish the block in hand, then stop. Its checkpoint is saved as usual.
+    fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    fn stopping(&self) -> bool {
+        *self.stop.borrow()
+    }
+
+    /// Resolves once [`stop`](Self::stop) has been called.
+    async fn stopped(&self) {
+        let _ = self.stop.subscribe().wait_for(|stop| *stop).await;
+    }
+
+    /// Poll for new blocks every 2 seconds, forever or until `limit` elapses.
+    async fn poll_blocks(&self, limit: Option<Duration>) {
         // Create interval timer for polling every 2 seconds
         // This runs continuously as a background worker
         let mut interval = time::interval(Duration::from_secs(2));
         let mut last_logged_block = 0u64;
+        let started = time::Instant::now();
 
         info!("Starting blockchain monitoring loop (polling every 2 seconds)");
 
-        loop {
-            interval.tick().await;
+        while limit.is_none_or(|limit| started.elapsed() < limit) {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.stopped() => break,
+            }
+            self.beat().await;
 
-            match self.provider.get_block_number().await {
+            match self.rpc.block_number().await {
                 Ok(current_block) => {
-                    let last_processed = *self.last_block.read().await;
+                    let last_processed = *self.scanned_block.read().await;
 
                     if current_block > last_processed {
                         // New blocks available
-                        if let Err(e) = self.check_new_blocks().await {
-                            // This should rarely happen now as errors are handled internally
-                            error!("Unexpected error checking blocks: {}", e);
-                        }
+                        self.process_new_head(current_block).await;
                     } else if current_block != last_logged_block {
                         // Log when we're waiting at a new block number
                         info!("Waiting for new blocks... (current: {})", current_block);
@@ -192,20 +876,84 @@ impl StablecoinMonitor {
         }
     }
 
-    async fn check_new_blocks(&self) -> Result<()> {
-        // Get latest block number
-        let latest_block = self.provider.get_block_number().await?;
-        let last_processed = *self.last_block.read().await;
+    async fn process_new_head(&self, head_block: u64) {
+        self.beat().await;
+        metrics().set_head(&self.chain, head_block);
+        if let Err(e) = self.check_new_blocks(head_block).await {
+            // This should rarely happen now as errors are handled internally
+            error!("Unexpected error checking blocks: {}", e);
+        }
+    }
+
+    async fn check_new_blocks(&self, head_block: u64) -> Result<()> {
+        self.retry_failed_blocks().await;
+
+        let last_processed = *self.scanned_block.read().await;
+
+        // In delayed mode only blocks that already meet the confirmation
+        // target are processed
+        let latest_block = match self.confirmations.mode {
+            ConfirmationMode::Delayed => {
+                self.confirmations
+                    .confirmed_head(&self.provider, head_block)
+                    .await?
+            }
+            ConfirmationMode::Head | ConfirmationMode::Dual => head_block,
+        };
+        *self.target_block.write().await = latest_block;
 
         // Only process if we have a new block
         if latest_block > last_processed {
+            // Far behind: sweep everything but the most recent blocks with
+            // range queries, then go block by block so reorgs are tracked
+            let tail = self.catchup_tail;
+            let last_processed = if latest_block - last_processed > tail {
+                let range_end = latest_block - tail;
+                self.catch_up_range(last_processed + 1, range_end).await;
+                range_end
+            } else {
+                last_processed
+            };
+
             // Process all blocks we're behind on (in case we missed some)
-            for block_num in (last_processed + 1)..=latest_block {
+            let mut block_num = last_processed + 1;
+            while block_num <= latest_block {
+                if self.stopping() {
+                    return Ok(());
+                }
                 info!("Processing block {}", block_num);
 
+                let header = match self.fetch_header(block_num).await {
+                    Ok(header) => Some(header),
+                    Err(e) => {
+                        warn!("Error fetching header for block {}: {}", block_num, e);
+                        None
+                    }
+                };
+
+                // A parent hash we don't recognise means the chain reorganised
+                // under us: rewind to the fork point and process again from there
+                if let Some(header) = header {
+                    let mismatch = self
+                        .reorg
+                        .lock()
+                        .await
+                        .parent_mismatch(block_num, header.parent_hash);
+                    if mismatch {
+                        let fork_point = self.handle_reorg(block_num).await;
+                        self.advance(fork_point).await;
+                        block_num = fork_point + 1;
+                        continue;
+                    }
+                }
+
                 // Use logs approach which is more reliable
-                match self.process_block_by_logs(block_num).await {
+                match self
+                    .process_block_by_logs(block_num, header.map(|header| header.hash))
+                    .await
+                {
                     Ok(tx_count) => {
+                        self.retries.lock().await.resolve(block_num);
                         if tx_count > 0 {
                             info!(
                                 "Block {} processed: {} stablecoin transfers found",
@@ -216,300 +964,1204 @@ impl StablecoinMonitor {
                             info!("Block {} processed: no stablecoin transfers", block_num);
                         }
                     }
-                    Err(e) => {
-                        // Log error but continue - don't propagate
-                        warn!(
-                            "Error processing block {} logs: {}. Skipping block.",
-                            block_num, e
-                        );
-                        // Continue processing other blocks even if one fails
-                    }
+                    // Keep going; the block is retried later and holds the
+                    // checkpoint back until it succeeds
+                    Err(e) => self.record_failure(block_num, e).await,
                 }
+
+                self.advance(block_num).await;
+                block_num += 1;
             }
 
-            // Update last processed block to the latest
-            *self.last_block.write().await = latest_block;
+            // Anything orphaned by a reorg that didn't reappear is gone for good
+            let retracted = self.reorg.lock().await.take_orphaned();
+            for (key, tx_data) in retracted {
+                self.retract(&key, tx_data).await;
+            }
 
             info!("Caught up to block {}", latest_block);
         }
 
+        if self.confirmations.mode != ConfirmationMode::Delayed {
+            self.publish_confirmed(head_block).await?;
+        }
+
         Ok(())
     }
 
-    async fn process_block_by_logs(&self, block_number: u64) -> Result<usize> {
-        // Create filter for Transfer events from our stablecoin addresses
-        let filter = Filter::new()
-            .from_block(block_number)
-            .to_block(block_number)
-            .address(vec![USDC_ADDRESS, USDT_ADDRESS, DAI_ADDRESS])
-            .event_signature(vec![TRANSFER_EVENT_SIGNATURE]);
+    /// Publish events that have reached the confirmation target with
+    /// `confirmed` status: pending transfers again, and the approval and
+    /// admin events held back until now.
+    async fn publish_confirmed(&self, head_block: u64) -> Result<()> {
+        if self.pending.lock().await.is_empty() {
+            return Ok(());
+        }
+        let confirmed_head = self
+            .confirmations
+            .confirmed_head(&self.provider, head_block)
+            .await?;
+        let confirmed = self.pending.lock().await.confirm_up_to(confirmed_head);
+        if !confirmed.is_empty() {
+            info!(
+                "{} events confirmed up to block {}",
+                confirmed.len(),
+                confirmed_head
+            );
+        }
+        for mut event in confirmed {
+            event.set_status(TransferStatus::Confirmed);
+            self.publish(event).await;
+        }
+        Ok(())
+    }
 
-        // Get logs - this may fail on some RPC providers with large blocks
-        let logs = match self.provider.get_logs(&filter).await {
-            Ok(logs) => logs,
-            Err(e) => {
-                // Check if this is a deserialization error (common with some RPC providers)
-                if e.to_string().contains("deserialization")
-                    || e.to_string().contains("BlockTransactions")
-                {
-                    // This might be an RPC provider issue, try a simpler approach
-                    warn!("RPC provider returned invalid block data for block {}. This is likely an RPC issue, not a code issue.", block_number);
-                    return Ok(0); // Gracefully skip this block
+    /// Process `from..=to` with batched `eth_getLogs` range queries, one
+    /// window of concurrent chunks at a time, checkpointing after each window.
+    async fn catch_up_range(&self, from: u64, to: u64) {
+        info!(
+            "Catching up on blocks {}-{} ({} blocks) with range queries",
+            from,
+            to,
+            to - from + 1
+        );
+
+        let started = Instant::now();
+        let mut start = from;
+        while start <= to && !self.stopping() {
+            let end = (start + self.range_fetcher.window_size() - 1).min(to);
+
+            let registry = self.registry.read().await.clone();
+            let filter = self.log_filter(&registry);
+
+            let fetched = self
+                .range_fetcher
+                .fetch(&self.rpc, &filter, start, end)
+                .await;
+            for (failed_start, failed_end, e) in &fetched.failed {
+                warn!(
+                    "Range query for blocks {}-{} failed: {:#}. Falling back to one block at a time for those blocks.",
+                    failed_start, failed_end, e
+                );
+            }
+
+            let mut by_block: BTreeMap<u64, Vec<Log>> = BTreeMap::new();
+            for log in fetched.logs {
+                if let Some(block_number) = log.block_number {
+                    by_block.entry(block_number).or_default().push(log);
                 }
-                return Err(e.into());
             }
-        };
 
-        let mut transfer_count = 0;
+            let mut transfer_count = 0;
+            let mut processed = 0;
+            for block_number in start..=end {
+                let refetch = fetched.failed.iter().any(|(failed_start, failed_end, _)| {
+                    (*failed_start..=*failed_end).contains(&block_number)
+                });
+                let result = if refetch {
+                    self.process_block_by_logs(block_number, None).await
+                } else {
+                    let logs = by_block.remove(&block_number).unwrap_or_default();
+                    self.process_logs(block_number, logs, &registry)
+                        .await
+                        .map(|published| {
+                            processed += 1;
+                            published.len()
+                        })
+                };
+                match result {
+                    Ok(published) => transfer_count += published,
+                    Err(e) => self.record_failure(block_number, e).await,
+                }
+            }
+            metrics()
+                .blocks_processed
+                .with_label_values(&[&self.chain])
+                .inc_by(processed);
+            info!(
+                "Blocks {}-{} processed: {} stablecoin transfers found ({})",
+                start,
+                end,
+                transfer_count,
+                progress(from, end, to, started.elapsed())
+            );
+
+            self.advance(end).await;
+            start = end + 1;
+        }
+    }
 
-        // Process logs
-        if logs.is_empty() {
-            // No stablecoin transfers in this block - this is normal
-            return Ok(0);
+    /// Publish every event in `from..=to`, after any progress saved by an
+    /// interrupted run, retrying failed blocks until they recover or run out
+    /// of attempts. Returns the blocks that were given up on, or returns
+    /// early once [`stop`](Self::stop) is called.
+    async fn backfill(&self, from: u64, to: u64) -> Vec<gaps::Gap> {
+        let start = self.resume_after.map_or(from, |block| block + 1);
+        if start > from {
+            info!("Blocks {}-{} were done in a previous run", from, start - 1);
+        }
+        if start <= to {
+            self.catch_up_range(start, to).await;
         }
 
-        for log in logs {
-            if let Some(stablecoin_info) = self.stablecoins.get(&log.address()) {
-                // Parse Transfer event
-                // Transfer(address indexed from, address indexed to, uint256 value)
-                if log.topics().len() >= 3 && log.data().data.len() >= 32 {
-                    let from_bytes: &[u8] = log.topics()[1].as_ref();
-                    let to_bytes: &[u8] = log.topics()[2].as_ref();
-                    let from = Address::from_slice(&from_bytes[12..]);
-                    let to = Address::from_slice(&to_bytes[12..]);
-                    let amount = U256::from_be_slice(&log.data().data);
-
-                    let tx_data = TransactionData {
-                        stablecoin: stablecoin_info.name.to_string(),
-                        amount: self.format_amount(amount, stablecoin_info.decimals),
-                        from: format!("{:?}", from),
-                        to: format!("{:?}", to),
-                        block_number,
-                        tx_hash: format!("{:#x}", log.transaction_hash.unwrap_or_default()),
-                    };
+        while !self.stopping() {
+            let retrying = self.retries.lock().await.first_retrying();
+            if retrying.is_none() {
+                break;
+            }
+            time::sleep(Duration::from_secs(1)).await;
+            self.retry_failed_blocks().await;
+        }
+        self.retries.lock().await.gaps()
+    }
+
+    /// Retry failed blocks whose backoff has elapsed.
+    async fn retry_failed_blocks(&self) {
+        let due = self.retries.lock().await.due();
+        if due.is_empty() {
+            return;
+        }
 
+        for block_num in due {
+            info!("Retrying block {}", block_num);
+            match self.process_block_by_logs(block_num, None).await {
+                Ok(tx_count) => {
                     info!(
-                        "Found {} transfer: from={} to={} amount={} tx_hash={} block={}",
-                        tx_data.stablecoin,
-                        tx_data.from,
-                        tx_data.to,
-                        tx_data.amount,
-                        tx_data.tx_hash,
-                        tx_data.block_number
+                        "Block {} recovered on retry: {} stablecoin transfers found",
+                        block_num, tx_count
                     );
+                    self.retries.lock().await.resolve(block_num);
+                }
+                Err(e) => self.record_failure(block_num, e).await,
+            }
+        }
 
-                    // Publish to Redis
-                    self.publish_to_redis(&tx_data).await;
+        // Recovered blocks may let the checkpoint move forward
+        let scanned = *self.scanned_block.read().await;
+        self.advance(scanned).await;
+    }
 
-                    // Also broadcast to WebSocket clients
-                    let _ = self.tx_broadcaster.send(tx_data);
+    async fn record_failure(&self, block_number: u64, e: eyre::Report) {
+        let abandoned = self
+            .retries
+            .lock()
+            .await
+            .fail(block_number, format!("{:#}", e));
+        if abandoned {
+            error!(
+                "Block {} keeps failing: {:#}. Its transfers are missing from the stream until a retry, every 5 minutes, succeeds.",
+                block_number, e
+            );
+        } else {
+            warn!(
+                "Error processing block {} logs: {:#}. Will retry.",
+                block_number, e
+            );
+        }
+    }
 
-                    transfer_count += 1;
-                }
-            }
+    /// Move the scan cursor to `scanned` and checkpoint the highest block
+    /// below which nothing is still waiting to be retried.
+    async fn advance(&self, scanned: u64) {
+        self.beat().await;
+        *self.scanned_block.write().await = scanned;
+
+        let (last_block, gaps) = {
+            let retries = self.retries.lock().await;
+            (retries.checkpoint_block(scanned), retries.gaps())
+        };
+        let unconfirmed_from = self.pending.lock().await.first_block();
+
+        *self.last_block.write().await = last_block;
+        metrics().set_last_block(&self.chain, last_block);
+        let checkpoint = Checkpoint {
+            last_block,
+            gaps,
+            unconfirmed_from,
+        };
+        if let Err(e) = self.checkpoint.save(self.chain_id, checkpoint).await {
+            warn!("Failed to save checkpoint at block {}: {:#}", last_block, e);
+        }
+    }
+
+    /// Note that the monitoring loop is still making progress.
+    async fn beat(&self) {
+        *self.heartbeat.write().await = Instant::now();
+    }
+
+    /// Component checks for `/health/live` and `/health/ready`. The monitor
+    /// is live while its loop keeps making progress, and ready while it's
+    /// also reaching the RPC, keeping up with the chain and connected to
+    /// Redis.
+    async fn probe(&self) -> Probe {
+        let since_heartbeat = self.heartbeat.read().await.elapsed();
+        let heartbeat_ok = since_heartbeat <= self.heartbeat_timeout;
+        let rpc_ok = self.rpc.reachable();
+        let lag = self
+            .target_block
+            .read()
+            .await
+            .saturating_sub(*self.scanned_block.read().await);
+        let lag_ok = lag <= self.max_lag_blocks;
+        let redis = self.sinks.connected("redis");
+
+        let mut checks = serde_json::json!({
+            "heartbeat": {
+                "ok": heartbeat_ok,
+                "seconds_ago": since_heartbeat.as_secs(),
+                "timeout_secs": self.heartbeat_timeout.as_secs(),
+            },
+            "rpc": { "ok": rpc_ok },
+            "lag": { "ok": lag_ok, "blocks": lag, "max": self.max_lag_blocks },
+        });
+        if let Some(connected) = redis {
+            checks["redis"] = serde_json::json!({ "ok": connected });
+        }
+
+        Probe {
+            live: heartbeat_ok,
+            ready: heartbeat_ok && rpc_ok && lag_ok && redis.unwrap_or(true),
+            body: serde_json::json!({ "chain": self.chain, "checks": checks }),
+        }
+    }
+
+    /// Progress and gaps, as reported by the health endpoint.
+    async fn health(&self) -> serde_json::Value {
+        let gaps = self.retries.lock().await.gaps();
+        let abandoned = gaps.iter().filter(|gap| gap.abandoned).count();
+        serde_json::json!({
+            "status": if abandoned > 0 { "degraded" } else { "ok" },
+            "chain": self.chain,
+            "chain_id": self.chain_id,
+            "last_block": *self.last_block.read().await,
+            "scanned_block": *self.scanned_block.read().await,
+            "retrying": gaps.len() - abandoned,
+            "abandoned": abandoned,
+            "gaps": gaps,
+            "rpc": self.rpc.status(),
+            "decode_failures": self.decode_failures.counts(),
+            "sinks": self.sinks.status(),
+        })
+    }
+
+    /// Fetch the canonical header at a height, caching it for the block's
+    /// logs.
+    async fn fetch_header(&self, block_number: u64) -> Result<BlockHeader> {
+        let block = self
+            .provider
+            .get_block_by_number(block_number.into(), BlockTransactionsKind::Hashes)
+            .await?
+            .ok_or_else(|| eyre::eyre!("block {} not found", block_number))?;
+        let header = BlockHeader {
+            number: block.header.number,
+            hash: block.header.hash,
+            parent_hash: block.header.parent_hash,
+            timestamp: block.header.timestamp,
+        };
+        self.headers.lock().await.insert(header);
+        Ok(header)
+    }
+
+    /// Header of the block a log belongs to, from the cache when possible.
+    async fn header_for(&self, block_number: u64, log: &Log) -> Result<BlockHeader> {
+        let Some(hash) = log.block_hash else {
+            return self.fetch_header(block_number).await;
+        };
+        if let Some(header) = self.headers.lock().await.get(&hash) {
+            return Ok(header);
+        }
+        let block = self
+            .provider
+            .get_block_by_hash(hash, BlockTransactionsKind::Hashes)
+            .await?
+            .ok_or_else(|| eyre::eyre!("block {} ({}) not found", block_number, hash))?;
+        let header = BlockHeader {
+            number: block.header.number,
+            hash,
+            parent_hash: block.header.parent_hash,
+            timestamp: block.header.timestamp,
+        };
+        self.headers.lock().await.insert(header);
+        Ok(header)
+    }
+
+    /// Walk back through the recent block buffer until our stored hash matches
+    /// the canonical chain again, and rewind to that point.
+    async fn handle_reorg(&self, block_number: u64) -> u64 {
+        let (oldest, fork_point) = {
+            let tracker = self.reorg.lock().await;
+            let fork_point = tracker
+                .fork_point(block_number, |number| async move {
+                    Ok(self.fetch_header(number).await?.hash)
+                })
+                .await;
+            (tracker.oldest().unwrap_or(block_number), fork_point)
+        };
+
+        if fork_point < oldest {
+            error!(
+                "Reorg at block {} is deeper than the {} blocks we track; rewinding all of them",
+                block_number,
+                block_number - oldest
+            );
+        }
+
+        let dropped = self.reorg.lock().await.rewind(fork_point);
+        self.pending.lock().await.rewind(fork_point);
+        warn!(
+            "Chain reorg detected at block {}: rewinding {} blocks to fork point {}",
+            block_number, dropped, fork_point
+        );
+        fork_point
+    }
+
+    async fn process_block_by_logs(
+        &self,
+        block_number: u64,
+        block_hash: Option<B256>,
+    ) -> Result<usize> {
+        // Snapshot the registry so a SIGHUP reload can't change it mid-block
+        let registry = self.registry.read().await.clone();
+
+        // Pinning the block hash guarantees the logs match the header we
+        // checked for reorgs
+        let filter = match block_hash {
+            Some(hash) => self.log_filter(&registry).at_block_hash(hash),
+            None => self
+                .log_filter(&registry)
+                .from_block(block_number)
+                .to_block(block_number),
+        };
+
+        // Get logs - this may fail on some RPC providers with large blocks,
+        // or with a deserialization error when the provider returns bad data.
+        // Either way the block goes to the retry queue rather than being
+        // treated as empty.
+        let logs = self
+            .rpc
+            .get_logs(&filter)
+            .await
+            .wrap_err_with(|| format!("eth_getLogs failed for block {}", block_number))?;
+
+        let published = self.process_logs(block_number, logs, &registry).await?;
+        metrics()
+            .blocks_processed
+            .with_label_values(&[&self.chain])
+            .inc();
+
+        let transfer_count = published.len();
+        if let Some(hash) = block_hash {
+            self.reorg
+                .lock()
+                .await
+                .record(block_number, hash, published);
         }
 
         Ok(transfer_count)
     }
 
-    fn format_amount(&self, amount: U256, decimals: u8) -> String {
-        let divisor = U256::from(10).pow(U256::from(decimals));
-        let whole = amount / divisor;
-        let fraction = amount % divisor;
-
-        match decimals {
-            6 => format!("{}.{:06}", whole, fraction),
-            18 => format!("{}.{:018}", whole, fraction),
-            n => {
-                let fraction_str = format!("{:0width$}", fraction, width = n as usize);
-                format!("{}.{}", whole, fraction_str)
-            }
-        }
-    }
-
-    async fn publish_to_redis(&self, tx_data: &TransactionData) {
-        if let Some(mut conn) = self.redis_conn.clone() {
-            // Serialize transaction data
-            if let Ok(json_data) = serde_json::to_string(tx_data) {
-                // Use Redis Streams for reliable message delivery
-                let stream_key = "stablecoin:transactions";
-
-                // Create entries for the stream
-                let block_str = tx_data.block_number.to_string();
-                let entries = vec![
-                    ("data", json_data.as_str()),
-                    ("stablecoin", &tx_data.stablecoin),
-                    ("amount", &tx_data.amount),
-                    ("from", &tx_data.from),
-                    ("to", &tx_data.to),
-                    ("block", &block_str),
-                    ("tx_hash", &tx_data.tx_hash),
-                ];
+    /// Filter for Transfer, approval and admin events from our stablecoin
+    /// addresses.
+    fn log_filter(&self, registry: &TokenRegistry) -> Filter {
+        Filter::new()
+            .address(registry.addresses())
+            .event_signature(events::signatures(self.decode_approvals))
+    }
 
-                // Add to main stream with automatic trimming to last 10000 entries
-                if let Err(e) = redis::cmd("XADD")
-                    .arg(stream_key)
-                    .arg("MAXLEN")
-                    .arg("~")
-                    .arg(10000)
-                    .arg("*")
-                    .arg(&entries)
-                    .query_async::<String>(&mut conn)
-                    .await
-                {
-                    error!("Failed to add to Redis stream {}: {}", stream_key, e);
+    /// Decode and publish the logs of one block, returning the transfers
+    /// that belong to it. Fails without publishing anything if the block's
+    /// header can't be fetched.
+    async fn process_logs(
+        &self,
+        block_number: u64,
+        logs: Vec<Log>,
+        registry: &TokenRegistry,
+    ) -> Result<Vec<(EventId, TransactionData)>> {
+        let mut published = Vec::new();
+        let mut senders = HashMap::new();
+
+        let header = match logs.first() {
+            Some(log) => self
+                .header_for(block_number, log)
+                .await
+                .wrap_err_with(|| format!("could not fetch header for block {}", block_number))?,
+            None => return Ok(published),
+        };
+
+        for log in logs {
+            let Some(stablecoin_info) = registry.get(&log.address()) else {
+                continue;
+            };
+
+            let decimals = stablecoin_info.decimals;
+            let decoded = match events::decode(&log, |amount| self.format_amount(amount, decimals))
+            {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    // Removed logs were already counted when first seen
+                    if !log.removed {
+                        self.decode_failures.record(&e);
+                        warn!(
+                            "Skipping {} log {} in block {}: {}",
+                            stablecoin_info.symbol,
+                            log.log_index.unwrap_or_default(),
+                            block_number,
+                            e
+                        );
+                    }
+                    continue;
+                }
+            };
+
+            let Decoded::Transfer { from, to, value } = decoded else {
+                // Everything but transfers waits for confirmation, so a
+                // removed log was never published
+                if log.removed {
+                    let key = EventId::new(
+                        &format!("{:#x}", log.transaction_hash.unwrap_or_default()),
+                        log.log_index.unwrap_or_default(),
+                    );
+                    self.pending.lock().await.remove(&key);
+                } else {
+                    self.publish_token_event(&log, decoded, stablecoin_info, &header, &mut senders)
+                        .await;
                 }
+                continue;
+            };
+
+            let tx_data = TransactionData {
+                stablecoin: stablecoin_info.symbol.clone(),
+                token: format!("{:?}", stablecoin_info.address),
+                amount: self.format_amount(value, decimals),
+                from: format!("{:?}", from),
+                to: format!("{:?}", to),
+                block_number,
+                block_hash: format!("{:#x}", header.hash),
+                timestamp: header.timestamp,
+                tx_hash: format!("{:#x}", log.transaction_hash.unwrap_or_default()),
+                tx_index: log.transaction_index.unwrap_or_default(),
+                log_index: log.log_index.unwrap_or_default(),
+                chain_id: self.chain_id,
+                event_kind: EventKind::classify(from, to),
+                status: self.confirmations.initial_status(),
+                raw_amount: value,
+                decimals,
+                usd_value: stablecoin_info
+                    .usd_rate()
+                    .map(|rate| format::to_f64(value, decimals) * rate),
+            };
+            let key = tx_data.id();
+
+            // The node tells us when a log it previously returned was
+            // reorged out
+            if log.removed {
+                let removed = self.reorg.lock().await.remove(block_number, &key);
+                self.retract(&key, removed.unwrap_or(tx_data)).await;
+                continue;
+            }
+
+            // Already published before a reorg moved it to this block. It's
+            // published again with its new block hash and timestamp, which
+            // give it a new publication ID, but counted toward supply once.
+            let moved = self.reorg.lock().await.reincluded(&key);
+            if moved {
+                info!(
+                    "{} {} in tx {} moved to block {} by a reorg",
+                    tx_data.stablecoin,
+                    tx_data.event_kind.as_str(),
+                    tx_data.tx_hash,
+                    tx_data.block_number
+                );
+            } else {
+                info!(
+                    "Found {} {}: from={} to={} amount={} tx_hash={} block={}",
+                    tx_data.stablecoin,
+                    tx_data.event_kind.as_str(),
+                    tx_data.from,
+                    tx_data.to,
+                    tx_data.amount,
+                    tx_data.tx_hash,
+                    tx_data.block_number
+                );
             }
+
+            if self.publish(StreamEvent::Transfer(tx_data.clone())).await && !moved {
+                self.record_supply(&tx_data, false).await;
+            }
+            self.track_pending(&key, &tx_data).await;
+            published.push((key, tx_data));
         }
+
+        Ok(published)
     }
-}
 
-async fn handle_websocket(
-    stream: TcpStream,
-    addr: SocketAddr,
-    mut rx: broadcast::Receiver<TransactionData>,
-) -> Result<()> {
-    info!("New WebSocket connection from: {}", addr);
-
-    let ws_stream = accept_async(stream).await?;
-    let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-
-    loop {
-        tokio::select! {
-            // Forward transaction data to client
-            Ok(tx_data) = rx.recv() => {
-                let json = serde_json::to_string(&tx_data)?;
-                if ws_sender.send(Message::Text(json)).await.is_err() {
-                    break;
-                }
+    /// Publish a decoded approval or admin event once it's confirmed. These
+    /// can't be retracted, so at the head they're held until a reorg can no
+    /// longer remove them.
+    async fn publish_token_event(
+        &self,
+        log: &Log,
+        decoded: Decoded,
+        stablecoin_info: &StablecoinInfo,
+        header: &BlockHeader,
+        senders: &mut HashMap<B256, Option<(Address, Bytes)>>,
+    ) {
+        let decimals = stablecoin_info.decimals;
+        let block_number = header.number;
+        let tx_hash = log.transaction_hash.unwrap_or_default();
+        let tx_index = log.transaction_index.unwrap_or_default();
+        let log_index = log.log_index.unwrap_or_default();
+
+        let event = match decoded {
+            Decoded::Approval(approval) => {
+                let via_permit = match self.transaction_sender(tx_hash, senders).await {
+                    Some((sender, input)) => events::is_permit(approval.owner, sender, &input),
+                    None => false,
+                };
+                StreamEvent::Approval(ApprovalData {
+                    stablecoin: stablecoin_info.symbol.clone(),
+                    owner: format!("{:?}", approval.owner),
+                    spender: format!("{:?}", approval.spender),
+                    amount: self.format_amount(approval.value, decimals),
+                    via_permit,
+                    block_number,
+                    block_hash: format!("{:#x}", header.hash),
+                    timestamp: header.timestamp,
+                    tx_hash: format!("{:#x}", tx_hash),
+                    tx_index,
+                    log_index,
+                    chain_id: self.chain_id,
+                    status: self.confirmations.initial_status(),
+                })
             }
+            // Handled by process_logs
+            Decoded::Transfer { .. } => return,
+            Decoded::Admin(action) => {
+                warn!(
+                    "{} issuer event {} account={} block={}",
+                    stablecoin_info.symbol,
+                    action.as_str(),
+                    action.account().unwrap_or("-"),
+                    block_number
+                );
+                StreamEvent::Admin(AdminEventData {
+                    stablecoin: stablecoin_info.symbol.clone(),
+                    action,
+                    block_number,
+                    block_hash: format!("{:#x}", header.hash),
+                    timestamp: header.timestamp,
+                    tx_hash: format!("{:#x}", tx_hash),
+                    tx_index,
+                    log_index,
+                    chain_id: self.chain_id,
+                    status: self.confirmations.initial_status(),
+                })
+            }
+        };
+
+        match self.confirmations.mode {
+            ConfirmationMode::Delayed => {
+                self.publish(event).await;
+            }
+            ConfirmationMode::Head | ConfirmationMode::Dual => {
+                let key = EventId::new(&format!("{:#x}", tx_hash), log_index);
+                self.pending.lock().await.insert(key, event);
+            }
+        }
+    }
+
+    /// Sender and calldata of a transaction, fetched once per block.
+    async fn transaction_sender(
+        &self,
+        tx_hash: B256,
+        senders: &mut HashMap<B256, Option<(Address, Bytes)>>,
+    ) -> Option<(Address, Bytes)> {
+        if let Some(sender) = senders.get(&tx_hash) {
+            return sender.clone();
+        }
+        let sender = match self.provider.get_transaction_by_hash(tx_hash).await {
+            Ok(Some(tx)) => Some((tx.from, tx.input().clone())),
+            Ok(None) => None,
+            Err(e) => {
+                debug!("Could not fetch transaction {:#x}: {}", tx_hash, e);
+                None
+            }
+        };
+        senders.insert(tx_hash, sender.clone());
+        sender
+    }
+
+    /// In dual mode, remember a pending transfer so it can be published again
+    /// once confirmed.
+    async fn track_pending(&self, key: &EventId, tx_data: &TransactionData) {
+        if self.confirmations.mode == ConfirmationMode::Dual {
+            self.pending
+                .lock()
+                .await
+                .insert(key.clone(), StreamEvent::Transfer(tx_data.clone()));
+        }
+    }
 
-            // Handle incoming messages from client
-            Some(msg) = ws_receiver.next() => {
-                match msg {
-                    Ok(Message::Close(_)) | Err(_) => {
-                        info!("Client {} disconnected", addr);
-                        break;
+    async fn retract(&self, key: &EventId, tx_data: TransactionData) {
+        self.pending.lock().await.remove(key);
+        warn!(
+            "Retracting {} transfer {} from block {}: no longer on the canonical chain",
+            tx_data.stablecoin, tx_data.tx_hash, tx_data.block_number
+        );
+        if self.publish(StreamEvent::Retracted(tx_data.clone())).await {
+            self.record_supply(&tx_data, true).await;
+        }
+    }
+
+    /// Update the token's running net supply change for a mint or burn, or
+    /// undo it for a retracted one, and publish the new total.
+    async fn record_supply(&self, tx_data: &TransactionData, retracted: bool) {
+        let Ok(amount) = I256::try_from(tx_data.raw_amount) else {
+            warn!(
+                "Not counting {} {} of {} in tx {} toward net supply: too large for a signed total",
+                tx_data.stablecoin,
+                tx_data.event_kind.as_str(),
+                tx_data.raw_amount,
+                tx_data.tx_hash
+            );
+            return;
+        };
+        let delta = match (tx_data.event_kind, retracted) {
+            (EventKind::Transfer, _) => return,
+            (EventKind::Mint, false) | (EventKind::Burn, true) => amount,
+            (EventKind::Mint, true) | (EventKind::Burn, false) => -amount,
+        };
+        let id = tx_data.id();
+        // Totals saved by a previous run are added in before anything is
+        // written back, so they aren't overwritten with this run's alone
+        let conn = self.redis_conn.as_ref().and_then(RedisConnection::get);
+        let (change, loaded) = {
+            let mut supply = self.supply.lock().await;
+            if let Some(conn) = &conn {
+                if !supply.is_loaded() {
+                    let registry = self.registry.read().await.clone();
+                    if let Some(stored) =
+                        Self::load_supply_totals(conn, tx_data.chain_id, &registry).await
+                    {
+                        supply.load(stored);
                     }
-                    Ok(Message::Ping(data)) => {
-                        let _ = ws_sender.send(Message::Pong(data)).await;
+                }
+                if !supply.knows(&id) {
+                    if let Some(counted) =
+                        Self::load_supply_counted(conn, tx_data.chain_id, &id).await
+                    {
+                        supply.remember(&id, counted);
                     }
-                    _ => {}
                 }
             }
+            (
+                supply.apply(&id, &tx_data.token, delta, retracted),
+                supply.is_loaded(),
+            )
+        };
+        let total = match change {
+            SupplyChange::Applied(total) => total,
+            // Published again after a failed delivery
+            SupplyChange::Unchanged => {
+                debug!(
+                    "{} {} in tx {} already {} net supply",
+                    tx_data.stablecoin,
+                    tx_data.event_kind.as_str(),
+                    tx_data.tx_hash,
+                    if retracted {
+                        "removed from"
+                    } else {
+                        "counted toward"
+                    }
+                );
+                return;
+            }
+            SupplyChange::Overflow => {
+                warn!(
+                    "Not counting {} {} in tx {} toward net supply: the total would overflow",
+                    tx_data.stablecoin,
+                    tx_data.event_kind.as_str(),
+                    tx_data.tx_hash
+                );
+                return;
+            }
+        };
+
+        let update = SupplyUpdate {
+            chain_id: tx_data.chain_id,
+            stablecoin: tx_data.stablecoin.clone(),
+            token: tx_data.token.clone(),
+            event_kind: tx_data.event_kind,
+            amount: self.format_signed(delta, tx_data.decimals),
+            net_change: self.format_signed(total, tx_data.decimals),
+            block_number: tx_data.block_number,
+            block_hash: tx_data.block_hash.clone(),
+            tx_hash: tx_data.tx_hash.clone(),
+            log_index: tx_data.log_index,
+            retracted,
+        };
+        info!(
+            "{} net supply change now {} ({} {})",
+            update.stablecoin,
+            update.net_change,
+            update.event_kind.as_str(),
+            update.amount
+        );
+        // Until the saved totals are in, the running total would be wrong
+        if !loaded {
+            return;
         }
+        if let Some(conn) = conn {
+            let ttl = self.dedup.ttl();
+            Self::save_supply_total(conn, &update, total, &id, ttl).await;
+        }
+        self.publish(StreamEvent::Supply(update)).await;
     }
 
-    Ok(())
-}
+    /// Hand an event to every sink unless it was already published within
+    /// the dedup window. Returns whether it was new. Its ID stays claimed
+    /// once every sink has delivered it; if any sink loses it, the claim is
+    /// released so a retry, reorg or restart can publish it again.
+    async fn publish(&self, event: StreamEvent) -> bool {
+        let id = event.publication_id();
+        if !self.dedup.claim(&id).await {
+            debug!("Skipping already published event {}", id);
+            return false;
+        }
+        metrics()
+            .events_published
+            .with_label_values(&[&self.chain, event.stablecoin(), event.kind()])
+            .inc();
+        let receipt = self.sinks.publish(event).await;
+        let dedup = self.dedup.clone();
+        tokio::spawn(
+            async move {
+                match receipt.await {
+                    Ok(true) => dedup.confirm(&id).await,
+                    _ => dedup.release(&id).await,
+                }
+            }
+            .in_current_span(),
+        );
+        true
+    }
 
-async fn start_websocket_server(tx_broadcaster: broadcast::Sender<TransactionData>) -> Result<()> {
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    let listener = TcpListener::bind(&addr).await?;
-    info!("WebSocket server listening on: {}", addr);
+    fn format_amount(&self, amount: U256, decimals: u8) -> String {
+        format::format_units(amount, decimals, self.amount_style)
+    }
 
-    loop {
-        let (stream, addr) = listener.accept().await?;
-        let rx = tx_broadcaster.subscribe();
+    fn format_signed(&self, amount: I256, decimals: u8) -> String {
+        format::format_signed(amount, decimals, self.amount_style)
+    }
 
-        tokio::spawn(async move {
-            if let Err(e) = handle_websocket(stream, addr, rx).await {
-                error!("WebSocket error for {}: {}", addr, e);
+    fn supply_totals_key(chain_id: u64) -> String {
+        format!("{}:{}", SUPPLY_TOTALS_KEY, chain_id)
+    }
+
+    fn supply_counted_key(chain_id: u64, id: &EventId) -> String {
+        format!("{}:{}:{}", SUPPLY_COUNTED_KEY, chain_id, id)
+    }
+
+    /// Net supply totals saved by previous runs, by token address. Totals
+    /// from before they were keyed by address are keyed by symbol; those are
+    /// moved to the address of the one token in `registry` with that symbol.
+    async fn load_supply_totals(
+        conn: &MultiplexedConnection,
+        chain_id: u64,
+        registry: &TokenRegistry,
+    ) -> Option<HashMap<String, I256>> {
+        let key = Self::supply_totals_key(chain_id);
+        let stored: HashMap<String, String> = match redis::cmd("HGETALL")
+            .arg(&key)
+            .query_async(&mut conn.clone())
+            .await
+        {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("Failed to load supply totals from {}: {}", key, e);
+                return None;
             }
-        });
+        };
+
+        let mut totals: HashMap<String, I256> = HashMap::new();
+        let mut by_symbol = Vec::new();
+        for (field, total) in stored {
+            let total = match I256::from_dec_str(&total) {
+                Ok(total) => total,
+                Err(e) => {
+                    warn!("Ignoring invalid supply total for {}: {}", field, e);
+                    continue;
+                }
+            };
+            if field.parse::<Address>().is_ok() {
+                totals.insert(field, total);
+            } else {
+                by_symbol.push((field, total));
+            }
+        }
+        if by_symbol.is_empty() {
+            return Some(totals);
+        }
+
+        let mut migration = redis::pipe();
+        migration.atomic();
+        for (symbol, total) in by_symbol {
+            let tokens: Vec<_> = registry
+                .tokens()
+                .into_iter()
+                .filter(|token| token.symbol == symbol)
+                .collect();
+            let [token] = tokens.as_slice() else {
+                warn!(
+                    "Leaving supply total for {} in {}: it matches {} tokens in the registry, not one",
+                    symbol,
+                    key,
+                    tokens.len()
+                );
+                continue;
+            };
+            let address = format!("{:?}", token.address);
+            let merged = totals.entry(address.clone()).or_default();
+            *merged = merged.saturating_add(total);
+            info!("Moving supply total for {} to token {}", symbol, address);
+            migration
+                .hset(&key, &address, merged.to_string())
+                .ignore()
+                .hdel(&key, &symbol)
+                .ignore();
+        }
+        if let Err(e) = migration.query_async::<()>(&mut conn.clone()).await {
+            warn!("Failed to move supply totals in {}: {}", key, e);
+            return None;
+        }
+        Some(totals)
+    }
+
+    /// Store the raw total for the next restart.
+    /// Save the token's new total along with whether `id` is now counted in
+    /// it, so a restart within `ttl` doesn't count it again.
+    async fn save_supply_total(
+        mut conn: MultiplexedConnection,
+        update: &SupplyUpdate,
+        total: I256,
+        id: &EventId,
+        ttl: Duration,
+    ) {
+        let key = Self::supply_totals_key(update.chain_id);
+        if let Err(e) = redis::pipe()
+            .atomic()
+            .hset(&key, &update.token, total.to_string())
+            .ignore()
+            .set_ex(
+                Self::supply_counted_key(update.chain_id, id),
+                if update.retracted { 0 } else { 1 },
+                ttl.as_secs().max(1),
+            )
+            .ignore()
+            .query_async::<()>(&mut conn)
+            .await
+        {
+            error!("Failed to save supply total to {}: {}", key, e);
+        }
     }
+
+    /// Whether a previous run counted `id` toward net supply, if it's
+    /// within the window.
+    async fn load_supply_counted(
+        conn: &MultiplexedConnection,
+        chain_id: u64,
+        id: &EventId,
+    ) -> Option<bool> {
+        let key = Self::supply_counted_key(chain_id, id);
+        match redis::cmd("GET")
+            .arg(&key)
+            .query_async::<Option<u8>>(&mut conn.clone())
+            .await
+        {
+            Ok(counted) => counted.map(|counted| counted == 1),
+            Err(e) => {
+                warn!("Failed to read {}: {}", key, e);
+                None
+            }
+        }
+    }
+}
+
+/// Result of [`StablecoinMonitor::probe`].
+struct Probe {
+    live: bool,
+    ready: bool,
+    body: serde_json::Value,
 }
 
-async fn start_health_server() -> Result<()> {
-    use tokio::io::AsyncWriteExt;
+/// `block-monitor backfill`: publish a historical block range to the chosen
+/// sinks, then exit.
+async fn backfill(args: BackfillArgs, config: Config) -> Result<()> {
+    if args.from > args.to {
+        eyre::bail!("--from {} is after --to {}", args.from, args.to);
+    }
+    if args
+        .sink
+        .split(',')
+        .any(|name| name.trim().eq_ignore_ascii_case("websocket"))
+    {
+        eyre::bail!("the websocket sink can't be used for a backfill; no WebSocket server runs");
+    }
+
+    let settings = match &args.chain {
+        Some(name) => config
+            .chains
+            .iter()
+            .find(|settings| &settings.chain.name == name)
+            .ok_or_else(|| eyre::eyre!("chain {} is not listed in CHAINS", name))?,
+        None if config.chains.len() == 1 => &config.chains[0],
+        None => eyre::bail!("CHAINS lists several chains; pick one with --chain"),
+    };
+    let chain = &settings.chain;
+
+    let progress_file = args.progress_file.unwrap_or_else(|| {
+        format!("backfill-{}-{}-{}.json", chain.name, args.from, args.to).into()
+    });
+    let mode = RunMode::Backfill {
+        from: args.from,
+        to: args.to,
+        sinks: args.sink,
+        progress_file: progress_file.clone(),
+    };
+
+    let (from, to) = (args.from, args.to);
+    async move {
+        let (tx_broadcaster, _) = broadcast::channel::<StreamEvent>(1);
+        let monitor = StablecoinMonitor::new(settings, &mode, tx_broadcaster).await?;
+
+        // On SIGINT or SIGTERM, finish the block in hand and keep the
+        // progress file
+        let stop_on_signal = async {
+            shutdown_signal().await;
+            info!("Received shutdown signal, stopping after the current block...");
+            monitor.stop();
+            future::pending::<Infallible>().await
+        };
+
+        info!("Backfilling blocks {}-{}", from, to);
+        let gaps = tokio::select! {
+            gaps = monitor.backfill(from, to) => gaps,
+            never = stop_on_signal => match never {},
+        };
 
-    let health_port = env::var("HEALTH_PORT").unwrap_or_else(|_| "8081".to_string());
-    let addr = format!("0.0.0.0:{}", health_port);
-    let listener = TcpListener::bind(&addr).await?;
-    info!("Health check server listening on: {}", addr);
+        // Don't exit with events still queued
+        monitor.sinks.close().await;
 
-    loop {
-        let (mut stream, _) = listener.accept().await?;
+        if monitor.stopping() {
+            eyre::bail!(
+                "backfill interrupted after block {}; run the same backfill again to resume",
+                *monitor.last_block.read().await
+            );
+        }
 
-        tokio::spawn(async move {
-            let response = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nOK";
-            let _ = stream.write_all(response.as_bytes()).await;
-        });
+        if !gaps.is_empty() {
+            eyre::bail!(
+                "{} blocks could not be processed: {:?}. Run the same backfill again to retry them.",
+                gaps.len(),
+                gaps.iter().map(|gap| gap.block).collect::<Vec<_>>()
+            );
+        }
+
+        // Every block was processed, but not every event got through. The
+        // progress file is kept, wound back to the start, so running the
+        // same backfill again republishes the range.
+        let losses = monitor.sinks.losses();
+        if !losses.is_empty() {
+            // Before block 0 there's nothing to save, so start afresh
+            match from.checked_sub(1) {
+                Some(before) => {
+                    monitor
+                        .checkpoint
+                        .save(
+                            monitor.chain_id,
+                            Checkpoint {
+                                last_block: before,
+                                ..Default::default()
+                            },
+                        )
+                        .await?
+                }
+                None => monitor.checkpoint.clear().await?,
+            }
+            eyre::bail!(
+                "some events were not delivered ({}). Progress in {} is reset to the start; run the same backfill again to republish the range.",
+                losses
+                    .iter()
+                    .map(|(sink, failed, dropped)| format!(
+                        "{}: {} failed, {} dropped",
+                        sink, failed, dropped
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+                progress_file.display()
+            );
+        }
+        monitor.checkpoint.clear().await?;
+        info!("Backfill of blocks {}-{} complete", from, to);
+        Ok(())
     }
+    .instrument(info_span!("chain", name = %chain.name))
+    .await
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Load environment variables
     dotenv::dotenv().ok();
+    let cli = Cli::parse();
 
-    // Initialize tracing
+    // Initialize tracing. Logs go to stderr so the stdout sink carries
+    // nothing but events.
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::from_default_env()
                 .add_directive(tracing::Level::INFO.into()),
         )
+        .with_writer(std::io::stderr)
         .init();
 
-    // Set up graceful shutdown
-    let shutdown = tokio::signal::ctrl_c();
-
-    // Get RPC URL from environment or use default
-    // MUST be a Base network RPC endpoint (not Ethereum mainnet)
-    let rpc_url = env::var("RPC_URL")
-        .or_else(|_| env::var("ALCHEMY_RPC_URL")) // Fallback for backward compatibility
-        .unwrap_or_else(|_| "https://mainnet.base.org".to_string());
+    // Check every setting before connecting to anything
+    let config = Config::load(cli.config.clone(), cli.overrides())?;
+    config.log_summary();
 
-    if rpc_url.contains("YOUR_API_KEY")
-        || rpc_url.contains("YOUR_PROJECT_ID")
-        || rpc_url.contains("YOUR_KEY")
-    {
-        error!("Please set RPC_URL environment variable with your Base network RPC endpoint");
-        error!("You can use providers like Alchemy, Infura, QuickNode, or the public Base RPC");
-        std::process::exit(1);
+    if let Some(Command::Backfill(args)) = cli.command {
+        return backfill(args, config).await;
     }
 
-    info!("Starting Block Monitor for Base Network");
-    info!("RPC URL: {}", rpc_url);
-    info!("Monitoring stablecoins:");
-    info!("  - USDC: 0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913");
-    info!("  - USDT: 0xfde4C96c8593536E31F229EA8f37b2ADa2699bb2");
-    info!("  - DAI:  0x50c5725949A6F0c72E6C4a641F24049A917DB0Cb");
-
-    // Create broadcast channel for transactions
-    let (tx_broadcaster, _) = broadcast::channel::<TransactionData>(100);
-
-    // Create monitor
-    let monitor = StablecoinMonitor::new(rpc_url, tx_broadcaster.clone()).await?;
-
-    // Start WebSocket server
-    let ws_handle = tokio::spawn(start_websocket_server(tx_broadcaster));
+    // One monitor per chain in CHAINS, each with its own RPC endpoints,
+    // token registry and checkpoint
+    let chains = &config.chains;
+    if chains.len() > 1 && cli.start_block.is_some() {
+        eyre::bail!(
+            "--start-block is ambiguous with several chains; set <CHAIN>_START_BLOCK instead"
+        );
+    }
 
-    // Start health check server
-    let health_handle = tokio::spawn(start_health_server());
+    info!(
+        "Starting Block Monitor for {}",
+        chains
+            .iter()
+            .map(|settings| settings.chain.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    // Create broadcast channel for transactions from every chain
+    let (tx_broadcaster, _) = broadcast::channel::<StreamEvent>(100);
+
+    // Create monitors
+    let mut monitors = Vec::with_capacity(chains.len());
+    for settings in chains {
+        let span = info_span!("chain", name = %settings.chain.name);
+        let mode = RunMode::Follow {
+            start_block: cli.start_block,
+        };
+        let monitor = StablecoinMonitor::new(settings, &mode, tx_broadcaster.clone())
+            .instrument(span.clone())
+            .await?;
+        span.in_scope(|| {
+            registry::spawn_reload_on_sighup(monitor.registry.clone(), monitor.metadata.clone())
+        })?;
+        monitors.push((Arc::new(monitor), span.clone()));
+    }
 
-    // Start monitoring
-    let monitor_handle = tokio::spawn(async move {
-        monitor.start_monitoring().await;
-    });
+    // Start the WebSocket, health and metrics server
+    let (stop_accepting, server_stopping) = watch::channel(false);
+    let (close_clients, clients_closing) = watch::channel(false);
+    let mut server_handle = tokio::spawn(server::serve(
+        config.port,
+        tx_broadcaster,
+        monitors
+            .iter()
+            .map(|(monitor, _)| monitor.clone())
+            .collect(),
+        server_stopping,
+        clients_closing,
+    ));
+
+    // Start monitoring every chain
+    let mut monitor_handles: Vec<_> = monitors
+        .iter()
+        .map(|(monitor, span)| {
+            let monitor = monitor.clone();
+            tokio::spawn(async move { monitor.start_monitoring().await }.instrument(span.clone()))
+        })
+        .collect();
 
     // Wait for tasks or shutdown signal
+    let mut server_running = true;
+    let mut stopped_monitor = None;
     tokio::select! {
-        _ = ws_handle => {
-            error!("WebSocket server stopped");
-        }
-        _ = health_handle => {
-            error!("Health server stopped");
+        result = &mut server_handle => {
+            server_running = false;
+            match result {
+                Ok(Err(e)) => error!("Server stopped: {:#}", e),
+                _ => error!("Server stopped"),
+            }
         }
-        _ = monitor_handle => {
+        (_, index, _) = future::select_all(monitor_handles.iter_mut()) => {
+            stopped_monitor = Some(index);
             error!("Monitor stopped");
         }
-        _ = shutdown => {
+        _ = shutdown_signal() => {
             info!("Received shutdown signal, stopping gracefully...");
         }
     }
+    if let Some(index) = stopped_monitor {
+        monitor_handles.remove(index);
+    }
+
+    // Stop taking connections and new blocks, let every chain finish the
+    // block in hand, deliver what's queued, then close WebSocket clients
+    let _ = stop_accepting.send(true);
+    for (monitor, _) in &monitors {
+        monitor.stop();
+    }
+    let drained = time::timeout(config.shutdown_timeout, async {
+        future::join_all(monitor_handles).await;
+        for (monitor, span) in &monitors {
+            monitor.sinks.close().instrument(span.clone()).await;
+        }
+        let _ = close_clients.send(true);
+        if server_running {
+            let _ = server_handle.await;
+        }
+    })
+    .await;
+    if drained.is_err() {
+        warn!(
+            "Shutdown didn't finish within {:?}; exiting anyway",
+            config.shutdown_timeout
+        );
+    }
 
     info!("Server shutdown complete");
     Ok(())
 }
+
+/// Resolves on SIGINT, or on SIGTERM as sent by the platform before it
+/// stops an instance.
+///
+/// game-server's `main.rs` has a copy; keep the two in sync.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Can't listen for SIGTERM: {}", e),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
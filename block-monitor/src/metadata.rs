@@ -0,0 +1,313 @@
+use crate::{format::MAX_DECIMALS, registry::TokenRegistry};
+use alloy::{
+    primitives::Address, providers::Provider, rpc::types::TransactionRequest, sol,
+    sol_types::SolCall,
+};
+use eyre::{bail, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tracing::{error, info, warn};
+
+sol! {
+    interface IERC20Metadata {
+        function name() external view returns (string);
+        function symbol() external view returns (string);
+        function decimals() external view returns (uint8);
+    }
+}
+
+/// Token metadata as reported by the token contract itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// Held while the cache file is read, merged and rewritten, as monitors for
+/// different chains can save at the same time, e.g. after a SIGHUP.
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// On-disk cache of token metadata keyed by `chain_id:address`, so restarts
+/// don't have to repeat the contract calls.
+struct MetadataCache {
+    path: PathBuf,
+    entries: HashMap<String, TokenMetadata>,
+}
+
+impl MetadataCache {
+    fn load(path: &Path) -> Self {
+        let entries = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring unreadable token metadata cache {}: {}",
+                    path.display(),
+                    e
+                );
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        Self {
+            path: path.to_path_buf(),
+            entries,
+        }
+    }
+
+    fn key(chain_id: u64, address: &Address) -> String {
+        format!("{}:{:#x}", chain_id, address)
+    }
+
+    fn save(&self) -> Result<()> {
+        let _lock = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Monitors for other chains share the file; keep their entries
+        let mut entries = Self::load(&self.path).entries;
+        entries.extend(self.entries.clone());
+        let json = serde_json::to_string_pretty(&entries)?;
+
+        // Replaced in one rename, so a crash can't leave it half-written
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, json)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .wrap_err_with(|| {
+                format!(
+                    "failed to write token metadata cache {}",
+                    self.path.display()
+                )
+            })
+    }
+}
+
+/// Checks registry entries against the `name()`, `symbol()` and `decimals()`
+/// the token contracts report on chain.
+pub struct MetadataVerifier {
+    provider: Arc<dyn Provider>,
+    cache: MetadataCache,
+    strict: bool,
+}
+
+impl MetadataVerifier {
+    /// With `strict` set, a configured decimals value that disagrees with the
+    /// chain is an error. Otherwise the on-chain value wins and a warning is
+    /// logged.
+    pub fn new(provider: Arc<dyn Provider>, cache_path: impl AsRef<Path>, strict: bool) -> Self {
+        Self {
+            provider,
+            cache: MetadataCache::load(cache_path.as_ref()),
+            strict,
+        }
+    }
+
+    async fn call<C: SolCall>(&self, address: Address, call: C) -> Result<C::Return> {
+        let tx = TransactionRequest::default()
+            .to(address)
+            .input(call.abi_encode().into());
+        let output = self.provider.call(&tx).await?;
+        Ok(C::abi_decode_returns(&output, true)?)
+    }
+
+    async fn fetch(&self, address: Address) -> Result<TokenMetadata> {
+        let name = self.call(address, IERC20Metadata::nameCall {}).await?._0;
+        let symbol = self.call(address, IERC20Metadata::symbolCall {}).await?._0;
+        let decimals = self
+            .call(address, IERC20Metadata::decimalsCall {})
+            .await?
+            ._0;
+        Ok(TokenMetadata {
+            name,
+            symbol,
+            decimals,
+        })
+    }
+
+    /// Fill in token names and verify decimals and symbols for every entry in
+    /// `registry`. Tokens whose metadata can't be fetched keep their
+    /// configured values.
+    pub async fn verify(&mut self, registry: &mut TokenRegistry) -> Result<()> {
+        let chain_id = registry.chain_id();
+        let mut cache_dirty = false;
+        let mut mismatches = Vec::new();
+
+        for token in registry.tokens_mut() {
+            let key = MetadataCache::key(chain_id, &token.address);
+            let metadata = match self.cache.entries.get(&key) {
+                Some(metadata) => metadata.clone(),
+                None => match self.fetch(token.address).await {
+                    Ok(metadata) => {
+                        info!(
+                            "Discovered on-chain metadata for {}: name={:?} symbol={:?} decimals={}",
+                            token.address, metadata.name, metadata.symbol, metadata.decimals
+                        );
+                        self.cache.entries.insert(key, metadata.clone());
+                        cache_dirty = true;
+                        metadata
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Could not read metadata for {} ({}): {}. Using configured values.",
+                            token.symbol, token.address, e
+                        );
+                        continue;
+                    }
+                },
+            };
+
+            if metadata.symbol != token.symbol {
+                warn!(
+                    "Token {} is configured as {} but reports symbol {} on chain",
+                    token.address, token.symbol, metadata.symbol
+                );
+            }
+
+            if metadata.decimals > MAX_DECIMALS {
+                error!(
+                    "{} ({}) reports {} decimals on chain, more than the {} amounts can be formatted with. Keeping the configured {}.",
+                    token.symbol,
+                    token.address,
+                    metadata.decimals,
+                    MAX_DECIMALS,
+                    token.decimals
+                );
+            } else if metadata.decimals != token.decimals {
+                error!(
+                    "DECIMALS MISMATCH for {} ({}): configured {}, on-chain {}. Amounts would be off by 10^{}.",
+                    token.symbol,
+                    token.address,
+                    token.decimals,
+                    metadata.decimals,
+                    token.decimals.abs_diff(metadata.decimals)
+                );
+                mismatches.push(token.symbol.clone());
+                if !self.strict {
+                    token.decimals = metadata.decimals;
+                }
+            }
+
+            if token.name.is_none() {
+                token.name = Some(metadata.name);
+            }
+        }
+
+        if cache_dirty {
+            if let Err(e) = self.cache.save() {
+                warn!("{:#}", e);
+            }
+        }
+
+        if self.strict && !mismatches.is_empty() {
+            bail!(
+                "configured decimals disagree with the chain for: {}",
+                mismatches.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::ProviderBuilder;
+
+    const USDC: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+
+    /// Verify a registry holding USDC with 6 decimals against `on_chain`
+    /// metadata served from the cache, or against an unreachable node when
+    /// there is none.
+    async fn verify(
+        name: &str,
+        on_chain: Option<(&str, u8)>,
+        strict: bool,
+    ) -> (Result<()>, TokenRegistry) {
+        let dir =
+            std::env::temp_dir().join(format!("metadata-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let registry_path = dir.join("tokens.toml");
+        std::fs::write(
+            &registry_path,
+            format!(
+                "[[tokens]]\naddress = \"{}\"\nsymbol = \"USDC\"\ndecimals = 6\nchain_id = 8453\n",
+                USDC
+            ),
+        )
+        .unwrap();
+        let cache_path = dir.join("token-metadata.json");
+        if let Some((symbol, decimals)) = on_chain {
+            std::fs::write(
+                &cache_path,
+                format!(
+                    r#"{{"8453:{}": {{"name": "USD Coin", "symbol": "{}", "decimals": {}}}}}"#,
+                    USDC, symbol, decimals
+                ),
+            )
+            .unwrap();
+        }
+
+        let mut registry = TokenRegistry::load(&registry_path, 8453).unwrap();
+        let provider = ProviderBuilder::new()
+            .on_http("http://127.0.0.1:1".parse().unwrap())
+            .boxed();
+        let mut verifier = MetadataVerifier::new(Arc::new(provider), &cache_path, strict);
+        let result = verifier.verify(&mut registry).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        (result, registry)
+    }
+
+    #[tokio::test]
+    async fn matching_metadata_fills_in_the_name() {
+        let (result, registry) = verify("match", Some(("USDC", 6)), true).await;
+        result.unwrap();
+        let token = registry.tokens()[0];
+        assert_eq!(token.decimals, 6);
+        assert_eq!(token.name.as_deref(), Some("USD Coin"));
+    }
+
+    #[tokio::test]
+    async fn strict_mode_rejects_mismatched_decimals() {
+        let (result, registry) = verify("strict", Some(("USDC", 18)), true).await;
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("USDC"));
+        assert_eq!(registry.tokens()[0].decimals, 6);
+    }
+
+    #[tokio::test]
+    async fn lenient_mode_uses_the_on_chain_decimals() {
+        let (result, registry) = verify("lenient", Some(("USDC", 18)), false).await;
+        result.unwrap();
+        assert_eq!(registry.tokens()[0].decimals, 18);
+    }
+
+    #[tokio::test]
+    async fn symbol_mismatches_are_only_logged() {
+        let (result, registry) = verify("symbol", Some(("USDbC", 6)), true).await;
+        result.unwrap();
+        assert_eq!(registry.tokens()[0].symbol, "USDC");
+    }
+
+    #[tokio::test]
+    async fn unreachable_tokens_keep_their_configured_values() {
+        let (result, registry) = verify("unreachable", None, true).await;
+        result.unwrap();
+        let token = registry.tokens()[0];
+        assert_eq!(token.decimals, 6);
+        assert_eq!(token.name, None);
+    }
+
+    #[tokio::test]
+    async fn out_of_range_decimals_on_chain_are_ignored() {
+        for strict in [true, false] {
+            let (result, registry) = verify("out-of-range", Some(("USDC", 200)), strict).await;
+            result.unwrap();
+            let token = registry.tokens()[0];
+            assert_eq!(token.decimals, 6);
+            assert_eq!(token.name.as_deref(), Some("USD Coin"));
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// Process-wide Prometheus metrics, served on `/metrics`.
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub struct Metrics {
+    registry: Registry,
+    /// Latest chain head seen, by chain
+    pub head_block: IntGaugeVec,
+    /// Checkpointed block, by chain
+    pub last_block: IntGaugeVec,
+    /// Blocks between the head and the checkpoint, by chain
+    pub lag_blocks: IntGaugeVec,
+    /// Blocks processed successfully, by chain
+    pub blocks_processed: IntCounterVec,
+    /// Events handed to the sinks, by chain, token and type
+    pub events_published: IntCounterVec,
+    /// RPC round trips, by endpoint and method
+    pub rpc_duration: HistogramVec,
+    /// Failed RPC requests, by endpoint and kind
+    pub rpc_errors: IntCounterVec,
+    /// Logs that couldn't be decoded, by chain and reason
+    pub decode_failures: IntCounterVec,
+    /// Failed delivery attempts, by chain and sink
+    pub sink_errors: IntCounterVec,
+    /// Events a sink gave up on or dropped, by chain, sink and reason
+    pub sink_lost: IntCounterVec,
+    /// `XADD`s that failed and went to the outbox, by chain
+    pub redis_failures: IntCounterVec,
+    /// Entries waiting in the Redis outbox, by chain
+    pub redis_outbox: IntGaugeVec,
+    /// Connected WebSocket clients
+    pub websocket_clients: IntGauge,
+    /// Events buffered for the slowest WebSocket client
+    pub broadcast_queue: IntGauge,
+    /// Events WebSocket clients missed by falling too far behind
+    pub broadcast_lagged: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("block_monitor".to_string()), None)
+            .expect("valid metrics prefix");
+
+        let gauge_vec = |name: &str, help: &str, labels: &[&str]| {
+            let gauge = IntGaugeVec::new(Opts::new(name, help), labels).expect("valid gauge");
+            registry
+                .register(Box::new(gauge.clone()))
+                .expect("unique metric name");
+            gauge
+        };
+        let counter_vec = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).expect("valid counter");
+            registry
+                .register(Box::new(counter.clone()))
+                .expect("unique metric name");
+            counter
+        };
+
+        let rpc_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "rpc_request_duration_seconds",
+                "RPC round trip time by endpoint and method",
+            ),
+            &["endpoint", "method"],
+        )
+        .expect("valid histogram");
+        registry
+            .register(Box::new(rpc_duration.clone()))
+            .expect("unique metric name");
+
+        let websocket_clients =
+            IntGauge::new("websocket_clients", "Connected WebSocket clients").expect("valid gauge");
+        let broadcast_queue = IntGauge::new(
+            "broadcast_queue_length",
+            "Events buffered for the slowest WebSocket client",
+        )
+        .expect("valid gauge");
+        let broadcast_lagged = IntCounter::new(
+            "broadcast_lagged_total",
+            "Events WebSocket clients missed by falling too far behind",
+        )
+        .expect("valid counter");
+        for collector in [
+            Box::new(websocket_clients.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(broadcast_queue.clone()),
+            Box::new(broadcast_lagged.clone()),
+        ] {
+            registry.register(collector).expect("unique metric name");
+        }
+
+        Self {
+            head_block: gauge_vec("head_block", "Latest chain head seen", &["chain"]),
+            last_block: gauge_vec("last_block", "Checkpointed block", &["chain"]),
+            lag_blocks: gauge_vec(
+                "lag_blocks",
+                "Blocks between the chain head and the checkpoint",
+                &["chain"],
+            ),
+            blocks_processed: counter_vec(
+                "blocks_processed_total",
+                "Blocks processed successfully",
+                &["chain"],
+            ),
+            events_published: counter_vec(
+                "events_published_total",
+                "Events handed to the sinks by token and type",
+                &["chain", "token", "type"],
+            ),
+            rpc_duration,
+            rpc_errors: counter_vec(
+                "rpc_errors_total",
+                "Failed RPC requests by endpoint and kind",
+                &["endpoint", "kind"],
+            ),
+            decode_failures: counter_vec(
+                "decode_failures_total",
+                "Token logs skipped because they couldn't be decoded",
+                &["chain", "reason"],
+            ),
+            sink_errors: counter_vec(
+                "sink_errors_total",
+                "Failed sink delivery attempts",
+                &["chain", "sink"],
+            ),
+            sink_lost: counter_vec(
+                "sink_events_lost_total",
+                "Events a sink gave up on (failed) or had no room for (dropped)",
+                &["chain", "sink", "reason"],
+            ),
+            redis_failures: counter_vec(
+                "redis_publish_failures_total",
+                "Redis stream writes that failed and went to the outbox",
+                &["chain"],
+            ),
+            redis_outbox: gauge_vec(
+                "redis_outbox_entries",
+                "Entries waiting in the Redis outbox",
+                &["chain"],
+            ),
+            websocket_clients,
+            broadcast_queue,
+            broadcast_lagged,
+            registry,
+        }
+    }
+
+    /// Record a new chain head.
+    pub fn set_head(&self, chain: &str, head: u64) {
+        self.head_block.with_label_values(&[chain]).set(head as i64);
+        self.update_lag(chain);
+    }
+
+    /// Record a new checkpoint.
+    pub fn set_last_block(&self, chain: &str, last_block: u64) {
+        self.last_block
+            .with_label_values(&[chain])
+            .set(last_block as i64);
+        self.update_lag(chain);
+    }
+
+    fn update_lag(&self, chain: &str) {
+        let head = self.head_block.with_label_values(&[chain]).get();
+        let last_block = self.last_block.with_label_values(&[chain]).get();
+        self.lag_blocks
+            .with_label_values(&[chain])
+            .set((head - last_block).max(0));
+    }
+
+    /// Every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
@@ -0,0 +1,262 @@
+use eyre::{Result, WrapErr};
+use std::path::{Path, PathBuf};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+};
+use tracing::warn;
+
+/// Flat `(field, value)` pairs of one Redis stream entry.
+pub type Entry = Vec<(String, String)>;
+
+/// Append-only file of stream entries that couldn't be written to Redis,
+/// one JSON array per line, oldest first.
+pub struct Outbox {
+    path: PathBuf,
+    len: usize,
+}
+
+impl Outbox {
+    /// Open the outbox at `path`, picking up entries left by a previous run.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut outbox = Self {
+            path: path.as_ref().to_path_buf(),
+            len: 0,
+        };
+        outbox.load().await?;
+        Ok(outbox)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Durably append an entry.
+    pub async fn push(&mut self, entry: &Entry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .wrap_err_with(|| format!("failed to open outbox {}", self.path.display()))?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Every buffered entry, oldest first.
+    pub async fn entries(&mut self) -> Result<Vec<Entry>> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.load().await
+    }
+
+    /// Drop the first `count` entries once they've been delivered. The rest
+    /// are rewritten to a temporary file and moved into place, so a crash
+    /// can't lose them.
+    pub async fn remove_front(&mut self, count: usize) -> Result<()> {
+        if count == 0 {
+            return Ok(());
+        }
+        let remaining = self.entries().await?.split_off(count.min(self.len));
+        self.rewrite(&remaining).await
+    }
+
+    /// Read the file and count its entries. Unreadable lines, such as one a
+    /// crash cut short, are moved to `<path>.corrupt` so they can't hold up
+    /// the entries behind them or keep the outbox from ever emptying.
+    async fn load(&mut self) -> Result<Vec<Entry>> {
+        let contents = match fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e)
+                    .wrap_err_with(|| format!("failed to read outbox {}", self.path.display()))
+            }
+        };
+
+        let mut entries = Vec::new();
+        let mut unreadable = Vec::new();
+        for line in contents.lines().filter(|line| !line.is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    warn!("Unreadable outbox entry: {}", e);
+                    unreadable.push(line);
+                }
+            }
+        }
+
+        // A missing final newline means the last write was cut short; the
+        // next append would run into it, so the file is rewritten whole.
+        if !unreadable.is_empty() || !contents.is_empty() && !contents.ends_with('\n') {
+            if !unreadable.is_empty() {
+                self.quarantine(&unreadable).await?;
+            }
+            self.rewrite(&entries).await?;
+        }
+        self.len = entries.len();
+        Ok(entries)
+    }
+
+    async fn quarantine(&self, lines: &[&str]) -> Result<()> {
+        let mut corrupt = self.path.clone().into_os_string();
+        corrupt.push(".corrupt");
+        let corrupt = PathBuf::from(corrupt);
+        warn!(
+            "Moving {} unreadable outbox entries to {}",
+            lines.len(),
+            corrupt.display()
+        );
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&corrupt)
+            .await
+            .wrap_err_with(|| format!("failed to open {}", corrupt.display()))?;
+        for line in lines {
+            file.write_all(line.as_bytes()).await?;
+            file.write_all(b"\n").await?;
+        }
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    /// Replace the file's contents with `entries`.
+    async fn rewrite(&mut self, entries: &[Entry]) -> Result<()> {
+        if entries.is_empty() {
+            fs::remove_file(&self.path)
+                .await
+                .or_else(|e| match e.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(e),
+                })?;
+            self.len = 0;
+            return Ok(());
+        }
+
+        let mut contents = Vec::new();
+        for entry in entries {
+            contents.extend(serde_json::to_vec(entry)?);
+            contents.push(b'\n');
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, contents).await?;
+        fs::rename(&tmp, &self.path).await?;
+        self.len = entries.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "block-monitor-outbox-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn corrupt_path(path: &Path) -> PathBuf {
+        let mut corrupt = path.to_path_buf().into_os_string();
+        corrupt.push(".corrupt");
+        PathBuf::from(corrupt)
+    }
+
+    fn entry(id: &str) -> Entry {
+        vec![("id".to_string(), id.to_string())]
+    }
+
+    #[tokio::test]
+    async fn entries_survive_a_reopen() {
+        let path = temp_path("reopen");
+        let _ = std::fs::remove_file(&path);
+
+        let mut outbox = Outbox::open(&path).await.unwrap();
+        assert!(outbox.is_empty());
+        outbox.push(&entry("a")).await.unwrap();
+        outbox.push(&entry("b")).await.unwrap();
+        outbox.push(&entry("c")).await.unwrap();
+        drop(outbox);
+
+        let mut outbox = Outbox::open(&path).await.unwrap();
+        assert_eq!(outbox.len(), 3);
+        assert_eq!(
+            outbox.entries().await.unwrap(),
+            vec![entry("a"), entry("b"), entry("c")]
+        );
+
+        outbox.remove_front(2).await.unwrap();
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox.entries().await.unwrap(), vec![entry("c")]);
+
+        outbox.remove_front(5).await.unwrap();
+        assert!(outbox.is_empty());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn unreadable_lines_are_quarantined() {
+        let path = temp_path("corrupt");
+        let corrupt = corrupt_path(&path);
+        let _ = std::fs::remove_file(&corrupt);
+        std::fs::write(
+            &path,
+            "[[\"id\",\"a\"]]\nnot json\n[[\"id\",\"b\"]]\n[[\"id\",",
+        )
+        .unwrap();
+
+        let mut outbox = Outbox::open(&path).await.unwrap();
+        assert_eq!(outbox.len(), 2);
+        assert_eq!(
+            outbox.entries().await.unwrap(),
+            vec![entry("a"), entry("b")]
+        );
+        assert_eq!(
+            std::fs::read_to_string(&corrupt).unwrap(),
+            "not json\n[[\"id\",\n"
+        );
+
+        // New entries land on a line of their own
+        outbox.push(&entry("c")).await.unwrap();
+        let mut outbox = Outbox::open(&path).await.unwrap();
+        assert_eq!(outbox.len(), 3);
+
+        outbox.remove_front(3).await.unwrap();
+        let _ = std::fs::remove_file(&corrupt);
+    }
+
+    #[tokio::test]
+    async fn missing_final_newline_is_repaired() {
+        let path = temp_path("newline");
+        std::fs::write(&path, "[[\"id\",\"a\"]]").unwrap();
+
+        let mut outbox = Outbox::open(&path).await.unwrap();
+        outbox.push(&entry("b")).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[[\"id\",\"a\"]]\n[[\"id\",\"b\"]]\n"
+        );
+        assert!(!corrupt_path(&path).exists());
+
+        outbox.remove_front(2).await.unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use redis::aio::MultiplexedConnection;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tracing::{info, warn, Instrument};
+
+/// How often the connection is checked, and replaced if it's gone
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Bounds each connection attempt and command, so an unreachable server
+/// fails fast instead of stalling whoever is waiting on it.
+const REDIS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The monitor's Redis connection, shared by the Redis sink, the checkpoint
+/// store, the dedup window and the supply totals. A background task pings it
+/// and reconnects when Redis goes away, so they all go back to Redis once
+/// it's reachable again.
+#[derive(Clone)]
+pub struct RedisConnection {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    client: redis::Client,
+    conn: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl RedisConnection {
+    /// Start from `conn`, or disconnected if there's none yet, and start the
+    /// reconnect loop.
+    pub fn start(client: redis::Client, conn: Option<MultiplexedConnection>) -> Self {
+        let shared = Arc::new(Shared {
+            client,
+            conn: Mutex::new(conn),
+        });
+        tokio::spawn(Self::reconnect_loop(Arc::downgrade(&shared)).in_current_span());
+        Self { shared }
+    }
+
+    /// Open a new connection with [`REDIS_TIMEOUT`] applied.
+    pub async fn connect(client: &redis::Client) -> redis::RedisResult<MultiplexedConnection> {
+        client
+            .get_multiplexed_async_connection_with_config(
+                &redis::AsyncConnectionConfig::new()
+                    .set_connection_timeout(REDIS_TIMEOUT)
+                    .set_response_timeout(REDIS_TIMEOUT),
+            )
+            .await
+    }
+
+    /// The current connection, or `None` while Redis is unreachable.
+    pub fn get(&self) -> Option<MultiplexedConnection> {
+        self.shared.conn().clone()
+    }
+
+    async fn reconnect_loop(shared: Weak<Shared>) {
+        loop {
+            tokio::time::sleep(RECONNECT_INTERVAL).await;
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+
+            let current = shared.conn().clone();
+            if let Some(mut conn) = current {
+                match redis::cmd("PING").query_async::<()>(&mut conn).await {
+                    Ok(()) => continue,
+                    Err(e) => {
+                        warn!("Redis disconnected: {}", e);
+                        *shared.conn() = None;
+                    }
+                }
+            }
+
+            match Self::connect(&shared.client).await {
+                Ok(conn) => {
+                    info!("Reconnected to Redis");
+                    *shared.conn() = Some(conn);
+                }
+                Err(e) => warn!("Redis still disconnected: {}", e),
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn conn(&self) -> std::sync::MutexGuard<'_, Option<MultiplexedConnection>> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{format::MAX_DECIMALS, metadata::MetadataVerifier};
+use alloy::primitives::Address;
+use eyre::{bail, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{error, info};
+
+/// A single token entry from the registry file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StablecoinInfo {
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+    pub chain_id: u64,
+    /// Full token name; filled in from the contract when not configured
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Display color used by the frontend, e.g. "#2775CA"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Free-form grouping such as "usd", "eur" or "bridged"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// USD value of one token, for the `usd_value` in published transfers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usd_rate: Option<f64>,
+}
+
+impl StablecoinInfo {
+    /// The configured rate, or 1.0 for tokens in the "usd" category.
+    pub fn usd_rate(&self) -> Option<f64> {
+        self.usd_rate
+            .or_else(|| (self.category.as_deref() == Some("usd")).then_some(1.0))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RegistryFile {
+    #[serde(default)]
+    tokens: Vec<StablecoinInfo>,
+}
+
+/// Tokens monitored on a single chain, keyed by contract address.
+#[derive(Debug, Clone)]
+pub struct TokenRegistry {
+    path: PathBuf,
+    chain_id: u64,
+    tokens: HashMap<Address, StablecoinInfo>,
+}
+
+pub type SharedRegistry = Arc<RwLock<TokenRegistry>>;
+
+impl TokenRegistry {
+    /// Load the registry from a `.toml` or `.json` file, keeping only the
+    /// tokens deployed on `chain_id`.
+    pub fn load(path: impl AsRef<Path>, chain_id: u64) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read token registry {}", path.display()))?;
+
+        let file: RegistryFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents)
+                .wrap_err_with(|| format!("invalid token registry {}", path.display()))?,
+            _ => toml::from_str(&contents)
+                .wrap_err_with(|| format!("invalid token registry {}", path.display()))?,
+        };
+
+        let mut tokens: HashMap<Address, StablecoinInfo> = HashMap::new();
+        for token in file.tokens {
+            if token.chain_id != chain_id {
+                continue;
+            }
+            if token.symbol.trim().is_empty() {
+                bail!("token {} has an empty symbol", token.address);
+            }
+            if token.decimals > MAX_DECIMALS {
+                bail!(
+                    "token {} has {} decimals (max {})",
+                    token.symbol,
+                    token.decimals,
+                    MAX_DECIMALS
+                );
+            }
+            if token
+                .usd_rate
+                .is_some_and(|rate| !rate.is_finite() || rate < 0.0)
+            {
+                bail!("token {} has an invalid usd_rate", token.symbol);
+            }
+            if let Some(existing) = tokens.get(&token.address) {
+                bail!(
+                    "address {} is registered twice ({} and {})",
+                    token.address,
+                    existing.symbol,
+                    token.symbol
+                );
+            }
+            // Symbols name the per-token streams, so they must be unique too
+            if let Some(existing) = tokens
+                .values()
+                .find(|existing| existing.symbol.eq_ignore_ascii_case(&token.symbol))
+            {
+                bail!(
+                    "symbol {} is registered twice ({} and {})",
+                    token.symbol,
+                    existing.address,
+                    token.address
+                );
+            }
+            tokens.insert(token.address, token);
+        }
+
+        if tokens.is_empty() {
+            bail!(
+                "token registry {} has no tokens for chain {}",
+                path.display(),
+                chain_id
+            );
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            chain_id,
+            tokens,
+        })
+    }
+
+    /// Re-read the file this registry was loaded from.
+    pub fn reload(&self) -> Result<Self> {
+        Self::load(&self.path, self.chain_id)
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub fn tokens_mut(&mut self) -> impl Iterator<Item = &mut StablecoinInfo> {
+        self.tokens.values_mut()
+    }
+
+    pub fn get(&self, address: &Address) -> Option<&StablecoinInfo> {
+        self.tokens.get(address)
+    }
+
+    pub fn addresses(&self) -> Vec<Address> {
+        self.tokens.keys().copied().collect()
+    }
+
+    /// Tokens sorted by symbol, for stable log output.
+    pub fn tokens(&self) -> Vec<&StablecoinInfo> {
+        let mut tokens: Vec<_> = self.tokens.values().collect();
+        tokens.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        tokens
+    }
+
+    pub fn log_summary(&self) {
+        info!(
+            "Monitoring {} tokens on chain {}:",
+            self.tokens.len(),
+            self.chain_id
+        );
+        for token in self.tokens() {
+            info!(
+                "  - {:<6} {:#x} ({} decimals{})",
+                token.symbol,
+                token.address,
+                token.decimals,
+                token
+                    .category
+                    .as_deref()
+                    .map(|c| format!(", {}", c))
+                    .unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Re-read and verify the registry file, replacing `registry` only if the
+/// new one is valid.
+#[cfg_attr(not(unix), allow(dead_code))]
+async fn reload(registry: &SharedRegistry, verifier: &Mutex<MetadataVerifier>) -> Result<()> {
+    let mut updated = registry.read().await.reload()?;
+    verifier.lock().await.verify(&mut updated).await?;
+    updated.log_summary();
+    *registry.write().await = updated;
+    Ok(())
+}
+
+/// Reload the registry whenever the process receives SIGHUP.
+///
+/// A file that fails to parse, validate or pass on-chain verification is
+/// logged and ignored, so a bad edit never takes down a running monitor.
+#[cfg(unix)]
+pub fn spawn_reload_on_sighup(
+    registry: SharedRegistry,
+    verifier: Arc<Mutex<MetadataVerifier>>,
+) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    use tracing::Instrument;
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    tokio::spawn(
+        async move {
+            while hangup.recv().await.is_some() {
+                info!("Received SIGHUP, reloading token registry");
+                if let Err(e) = reload(&registry, &verifier).await {
+                    error!("Token registry reload failed, keeping previous: {:#}", e);
+                }
+            }
+        }
+        .in_current_span(),
+    );
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn spawn_reload_on_sighup(
+    _registry: SharedRegistry,
+    _verifier: Arc<Mutex<MetadataVerifier>>,
+) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::providers::ProviderBuilder;
+
+    const USDC: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+    const USDBC: &str = "0xd9aaec86b65d86f6a7b5b1b0c42ffa531710b6ca";
+
+    fn token(address: &str, symbol: &str, chain_id: u64) -> String {
+        format!(
+            "[[tokens]]\naddress = \"{}\"\nsymbol = \"{}\"\ndecimals = 6\nchain_id = {}\n",
+            address, symbol, chain_id
+        )
+    }
+
+    fn write_registry(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "block-monitor-registry-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn load(name: &str, contents: &str) -> Result<TokenRegistry> {
+        let path = write_registry(name, contents);
+        let registry = TokenRegistry::load(&path, 8453);
+        std::fs::remove_file(&path).unwrap();
+        registry
+    }
+
+    #[test]
+    fn only_tokens_for_the_chain_are_kept() {
+        let registry = load(
+            "chain",
+            &[token(USDC, "USDC", 8453), token(USDBC, "USDbC", 1)].concat(),
+        )
+        .unwrap();
+        assert_eq!(registry.addresses(), [USDC.parse::<Address>().unwrap()]);
+        assert_eq!(registry.tokens()[0].usd_rate(), None);
+
+        let err = load("no-tokens", &token(USDC, "USDC", 1)).unwrap_err();
+        assert!(err.to_string().contains("no tokens for chain 8453"));
+    }
+
+    #[test]
+    fn json_registries_are_accepted() {
+        let path = std::env::temp_dir().join(format!(
+            "block-monitor-registry-json-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            format!(
+                r#"{{"tokens": [{{"address": "{}", "symbol": "USDC", "decimals": 6, "chain_id": 8453, "category": "usd"}}]}}"#,
+                USDC
+            ),
+        )
+        .unwrap();
+        let registry = TokenRegistry::load(&path, 8453).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(registry.tokens()[0].usd_rate(), Some(1.0));
+    }
+
+    #[test]
+    fn duplicate_addresses_are_rejected() {
+        let err = load(
+            "dup-address",
+            &[token(USDC, "USDC", 8453), token(USDC, "USDbC", 8453)].concat(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is registered twice"));
+    }
+
+    #[test]
+    fn duplicate_symbols_are_rejected() {
+        let err = load(
+            "dup-symbol",
+            &[token(USDC, "USDC", 8453), token(USDBC, "usdc", 8453)].concat(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("symbol usdc is registered twice"));
+
+        // The same symbol on another chain is fine
+        load(
+            "dup-symbol-other-chain",
+            &[token(USDC, "USDC", 8453), token(USDBC, "USDC", 1)].concat(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        assert!(load("empty-symbol", &token(USDC, " ", 8453)).is_err());
+        let too_precise = token(USDC, "USDC", 8453).replace("decimals = 6", "decimals = 78");
+        assert!(load("decimals", &too_precise).is_err());
+        let negative_rate = token(USDC, "USDC", 8453) + "usd_rate = -1.0\n";
+        assert!(load("usd-rate", &negative_rate).is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_reload_keeps_the_previous_registry() {
+        let path = write_registry("reload", &token(USDC, "USDC", 8453));
+        let cache_path = path.with_extension("metadata.json");
+        std::fs::write(
+            &cache_path,
+            format!(
+                r#"{{"8453:{}": {{"name": "USD Coin", "symbol": "USDC", "decimals": 6}},
+                    "8453:{}": {{"name": "USD Base Coin", "symbol": "USDbC", "decimals": 6}}}}"#,
+                USDC, USDBC
+            ),
+        )
+        .unwrap();
+        // Never called, as the metadata comes from the cache
+        let provider = ProviderBuilder::new()
+            .on_http("http://127.0.0.1:1".parse().unwrap())
+            .boxed();
+        let verifier = Mutex::new(MetadataVerifier::new(Arc::new(provider), &cache_path, true));
+        let registry: SharedRegistry =
+            Arc::new(RwLock::new(TokenRegistry::load(&path, 8453).unwrap()));
+
+        std::fs::write(&path, "[[tokens]\n").unwrap();
+        assert!(reload(&registry, &verifier).await.is_err());
+        assert_eq!(registry.read().await.tokens().len(), 1);
+
+        std::fs::write(
+            &path,
+            [token(USDC, "USDC", 8453), token(USDBC, "USDbC", 8453)].concat(),
+        )
+        .unwrap();
+        reload(&registry, &verifier).await.unwrap();
+        assert_eq!(registry.read().await.tokens().len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&cache_path).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+use crate::{events::EventId, TransactionData};
+use alloy::primitives::B256;
+use eyre::Result;
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+};
+use tracing::warn;
+
+struct BlockRecord {
+    number: u64,
+    hash: B256,
+    events: Vec<(EventId, TransactionData)>,
+}
+
+/// Ring buffer of recently processed block hashes and the transfers
+/// published for each, used to detect reorgs and work out which transfers
+/// need to be retracted.
+pub struct ReorgTracker {
+    capacity: usize,
+    blocks: VecDeque<BlockRecord>,
+    /// Transfers from rewound blocks that haven't been seen again yet
+    orphaned: HashMap<EventId, TransactionData>,
+}
+
+impl ReorgTracker {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            blocks: VecDeque::with_capacity(capacity),
+            orphaned: HashMap::new(),
+        }
+    }
+
+    pub fn hash_of(&self, number: u64) -> Option<B256> {
+        self.blocks
+            .iter()
+            .rev()
+            .find(|record| record.number == number)
+            .map(|record| record.hash)
+    }
+
+    pub fn oldest(&self) -> Option<u64> {
+        self.blocks.front().map(|record| record.number)
+    }
+
+    /// True if we've seen `number - 1` and its hash isn't `parent_hash`.
+    pub fn parent_mismatch(&self, number: u64, parent_hash: B256) -> bool {
+        number
+            .checked_sub(1)
+            .and_then(|parent| self.hash_of(parent))
+            .is_some_and(|known| known != parent_hash)
+    }
+
+    /// Where the chain forked below `block_number`: the newest tracked block
+    /// whose hash still matches `canonical`, the canonical hash at a height.
+    /// If none matches, or `canonical` fails, every tracked block is treated
+    /// as reorged and the block before the oldest is returned.
+    pub async fn fork_point<F, Fut>(&self, block_number: u64, mut canonical: F) -> u64
+    where
+        F: FnMut(u64) -> Fut,
+        Fut: Future<Output = Result<B256>>,
+    {
+        let oldest = self.oldest().unwrap_or(block_number);
+        for record in self
+            .blocks
+            .iter()
+            .rev()
+            .filter(|record| record.number < block_number)
+        {
+            match canonical(record.number).await {
+                Ok(hash) if hash == record.hash => return record.number,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!(
+                        "Error fetching header for block {} during reorg: {}",
+                        record.number, e
+                    );
+                    break;
+                }
+            }
+        }
+        oldest.saturating_sub(1)
+    }
+
+    /// Drop every block after `fork_point`, moving their transfers into the
+    /// orphaned set. Returns the number of blocks dropped.
+    pub fn rewind(&mut self, fork_point: u64) -> usize {
+        let mut dropped = 0;
+        while self
+            .blocks
+            .back()
+            .is_some_and(|record| record.number > fork_point)
+        {
+            if let Some(record) = self.blocks.pop_back() {
+                self.orphaned.extend(record.events);
+                dropped += 1;
+            }
+        }
+        dropped
+    }
+
+    pub fn record(&mut self, number: u64, hash: B256, events: Vec<(EventId, TransactionData)>) {
+        // A re-processed block replaces whatever we had for that height
+        while self
+            .blocks
+            .back()
+            .is_some_and(|record| record.number >= number)
+        {
+            self.blocks.pop_back();
+        }
+        self.blocks.push_back(BlockRecord {
+            number,
+            hash,
+            events,
+        });
+        while self.blocks.len() > self.capacity {
+            self.blocks.pop_front();
+        }
+    }
+
+    /// Returns true if the transfer was orphaned by a reorg and has now been
+    /// re-included, so it's no longer retracted. It's still published again
+    /// under its new block, but already counts toward net supply.
+    pub fn reincluded(&mut self, key: &EventId) -> bool {
+        self.orphaned.remove(key).is_some()
+    }
+
+    /// Forget a transfer whose log was flagged `removed` by the node.
+    pub fn remove(&mut self, number: u64, key: &EventId) -> Option<TransactionData> {
+        if let Some(data) = self.orphaned.remove(key) {
+            return Some(data);
+        }
+        let record = self
+            .blocks
+            .iter_mut()
+            .rev()
+            .find(|record| record.number == number)?;
+        let index = record.events.iter().position(|(k, _)| k == key)?;
+        Some(record.events.remove(index).1)
+    }
+
+    /// Orphaned transfers that never reappeared on the canonical chain.
+    pub fn take_orphaned(&mut self) -> Vec<(EventId, TransactionData)> {
+        let mut orphaned: Vec<_> = self.orphaned.drain().collect();
+        orphaned.sort_by_key(|(_, data)| data.block_number);
+        orphaned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(number: u64) -> B256 {
+        B256::with_last_byte(number as u8)
+    }
+
+    fn reorged(number: u64) -> B256 {
+        B256::repeat_byte(number as u8)
+    }
+
+    fn transfer(block: u64) -> (EventId, TransactionData) {
+        let tx_data = TransactionData::example(block, 0);
+        (tx_data.id(), tx_data)
+    }
+
+    /// Blocks `numbers` recorded with one transfer each.
+    fn tracker(capacity: usize, numbers: std::ops::RangeInclusive<u64>) -> ReorgTracker {
+        let mut tracker = ReorgTracker::new(capacity);
+        for number in numbers {
+            tracker.record(number, hash(number), vec![transfer(number)]);
+        }
+        tracker
+    }
+
+    #[tokio::test]
+    async fn parent_mismatch_finds_the_fork_point() {
+        let tracker = tracker(64, 10..=14);
+        assert!(!tracker.parent_mismatch(15, hash(14)));
+        assert!(tracker.parent_mismatch(15, reorged(14)));
+        // Nothing to compare against
+        assert!(!tracker.parent_mismatch(20, reorged(19)));
+
+        // 13 and 14 were replaced on the canonical chain
+        let canonical = |number: u64| async move {
+            Ok(if number >= 13 {
+                reorged(number)
+            } else {
+                hash(number)
+            })
+        };
+        assert_eq!(tracker.fork_point(15, canonical).await, 12);
+    }
+
+    #[tokio::test]
+    async fn fork_point_below_every_tracked_block() {
+        let tracker = tracker(64, 10..=14);
+        let canonical = |number: u64| async move { Ok(reorged(number)) };
+        assert_eq!(tracker.fork_point(15, canonical).await, 9);
+
+        // A header that can't be fetched stops the walk
+        let canonical = |number: u64| async move {
+            match number {
+                14 => Ok(reorged(14)),
+                _ => Err(eyre::eyre!("connection refused")),
+            }
+        };
+        assert_eq!(tracker.fork_point(15, canonical).await, 9);
+    }
+
+    #[test]
+    fn rewind_orphans_the_dropped_blocks_transfers() {
+        let mut tracker = tracker(64, 10..=14);
+        assert_eq!(tracker.rewind(12), 2);
+        assert_eq!(tracker.hash_of(12), Some(hash(12)));
+        assert_eq!(tracker.hash_of(13), None);
+
+        let orphaned: Vec<u64> = tracker
+            .take_orphaned()
+            .into_iter()
+            .map(|(_, tx_data)| tx_data.block_number)
+            .collect();
+        assert_eq!(orphaned, vec![13, 14]);
+        assert!(tracker.take_orphaned().is_empty());
+    }
+
+    #[test]
+    fn oldest_blocks_are_evicted_past_capacity() {
+        let mut tracker = tracker(3, 1..=5);
+        assert_eq!(tracker.oldest(), Some(3));
+        assert_eq!(tracker.hash_of(2), None);
+        assert_eq!(tracker.hash_of(5), Some(hash(5)));
+
+        // Recording a height again replaces it and everything above it
+        tracker.record(4, reorged(4), Vec::new());
+        assert_eq!(tracker.hash_of(4), Some(reorged(4)));
+        assert_eq!(tracker.hash_of(5), None);
+        assert_eq!(tracker.oldest(), Some(3));
+    }
+
+    #[test]
+    fn reincluded_transfers_are_not_retracted() {
+        let mut tracker = tracker(64, 10..=14);
+        tracker.rewind(12);
+        let (moved, _) = transfer(13);
+        let (unknown, _) = transfer(99);
+
+        assert!(tracker.reincluded(&moved));
+        assert!(!tracker.reincluded(&moved));
+        assert!(!tracker.reincluded(&unknown));
+
+        let orphaned: Vec<u64> = tracker
+            .take_orphaned()
+            .into_iter()
+            .map(|(_, tx_data)| tx_data.block_number)
+            .collect();
+        assert_eq!(orphaned, vec![14]);
+    }
+}
@@ -0,0 +1,700 @@
+use crate::{config::redact, metrics::metrics};
+use alloy::{
+    providers::{Provider, ProviderBuilder},
+    rpc::{
+        client::RpcClient,
+        json_rpc::{RequestPacket, ResponsePacket},
+        types::{Filter, Log},
+    },
+    transports::{
+        http::{
+            reqwest::{self, Client},
+            Http,
+        },
+        RpcError, TransportError, TransportErrorKind, TransportFut,
+    },
+};
+use eyre::{bail, Result, WrapErr};
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::Service;
+use tracing::{info, warn};
+
+/// Consecutive failures before an endpoint is benched
+const FAILURE_THRESHOLD: u32 = 3;
+const BASE_COOLDOWN: Duration = Duration::from_secs(10);
+const MAX_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Weight of the newest sample in the success and latency moving averages
+const EWMA_WEIGHT: f64 = 0.2;
+
+struct EndpointHealth {
+    /// Moving average of request success, from 0.0 to 1.0
+    score: f64,
+    latency_ms: f64,
+    consecutive_failures: u32,
+    benched_until: Option<Instant>,
+    requests: u64,
+    failures: u64,
+    last_error: Option<String>,
+}
+
+struct Endpoint {
+    /// Host and port only, so API keys in the URL path never reach logs
+    name: String,
+    transport: Http<Client>,
+    health: Mutex<EndpointHealth>,
+}
+
+impl Endpoint {
+    fn health(&self) -> std::sync::MutexGuard<'_, EndpointHealth> {
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn succeeded(&self, latency: Duration) {
+        let mut health = self.health();
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        health.latency_ms = if health.requests == 0 {
+            latency_ms
+        } else {
+            health.latency_ms * (1.0 - EWMA_WEIGHT) + latency_ms * EWMA_WEIGHT
+        };
+        health.score = health.score * (1.0 - EWMA_WEIGHT) + EWMA_WEIGHT;
+        health.requests += 1;
+        health.consecutive_failures = 0;
+        health.benched_until = None;
+    }
+
+    fn failed(&self, error: String) {
+        let mut health = self.health();
+        health.score *= 1.0 - EWMA_WEIGHT;
+        health.requests += 1;
+        health.failures += 1;
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= FAILURE_THRESHOLD {
+            let cooldown = BASE_COOLDOWN
+                .saturating_mul(
+                    2u32.saturating_pow(health.consecutive_failures - FAILURE_THRESHOLD),
+                )
+                .min(MAX_COOLDOWN);
+            if health.benched_until.is_none() {
+                warn!(
+                    "RPC endpoint {} failed {} times in a row ({}); benching it for {:?}",
+                    self.name, health.consecutive_failures, error, cooldown
+                );
+            }
+            health.benched_until = Some(Instant::now() + cooldown);
+        }
+        health.last_error = Some(error);
+    }
+
+    /// Lower is better: latency inflated by the failure rate.
+    fn rank(&self) -> f64 {
+        let health = self.health();
+        health.latency_ms / health.score.max(0.01)
+    }
+
+    fn benched_until(&self) -> Option<Instant> {
+        self.health()
+            .benched_until
+            .filter(|until| *until > Instant::now())
+    }
+}
+
+/// Transport that sends each request to the healthiest endpoint and fails
+/// over to the next one when a request errors or is rate limited.
+#[derive(Clone)]
+pub struct FailoverTransport {
+    endpoints: Arc<Vec<Endpoint>>,
+    /// Endpoint that answered the last request
+    active: Arc<AtomicUsize>,
+    /// Restrict requests to a single endpoint, for quorum checks
+    pinned: Option<usize>,
+}
+
+impl FailoverTransport {
+    /// Endpoint indices in the order they should be tried: available ones by
+    /// rank, then benched ones soonest-available first as a last resort.
+    fn ranked(&self) -> Vec<usize> {
+        if let Some(index) = self.pinned {
+            return vec![index];
+        }
+
+        let (mut available, mut benched): (Vec<_>, Vec<_>) = (0..self.endpoints.len())
+            .map(|index| (index, self.endpoints[index].benched_until()))
+            .partition(|(_, benched_until)| benched_until.is_none());
+        available.sort_by(|(a, _), (b, _)| {
+            self.endpoints[*a]
+                .rank()
+                .total_cmp(&self.endpoints[*b].rank())
+        });
+        benched.sort_by_key(|(_, benched_until)| *benched_until);
+        available
+            .into_iter()
+            .chain(benched)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    async fn dispatch(self, request: RequestPacket) -> Result<ResponsePacket, TransportError> {
+        let mut last = None;
+
+        let method = match &request {
+            RequestPacket::Single(request) => request.method(),
+            RequestPacket::Batch(_) => "batch",
+        };
+
+        for index in self.ranked() {
+            let endpoint = &self.endpoints[index];
+            let started = Instant::now();
+            match endpoint.transport.clone().call(request.clone()).await {
+                Ok(response) if is_rate_limited(&response) => {
+                    record_error(&endpoint.name, "rate_limited");
+                    endpoint.failed("rate limited".to_string());
+                    last = Some(Ok(response));
+                }
+                Ok(response) => {
+                    metrics()
+                        .rpc_duration
+                        .with_label_values(&[&endpoint.name, method])
+                        .observe(started.elapsed().as_secs_f64());
+                    if response.is_error() {
+                        record_error(&endpoint.name, "error_response");
+                    }
+                    endpoint.succeeded(started.elapsed());
+                    if self.pinned.is_none() {
+                        let previous = self.active.swap(index, Ordering::Relaxed);
+                        if previous != index {
+                            info!(
+                                "RPC requests now going to {} (was {})",
+                                endpoint.name, self.endpoints[previous].name
+                            );
+                        }
+                    }
+                    return Ok(response);
+                }
+                Err(e) => {
+                    let e = without_url(e);
+                    record_error(&endpoint.name, error_kind(&e));
+                    endpoint.failed(e.to_string());
+                    last = Some(Err(e));
+                }
+            }
+        }
+
+        last.unwrap_or_else(|| {
+            Err(TransportErrorKind::custom_str(
+                "no RPC endpoints configured",
+            ))
+        })
+    }
+}
+
+impl Service<RequestPacket> for FailoverTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        Box::pin(self.clone().dispatch(request))
+    }
+}
+
+fn record_error(endpoint: &str, kind: &str) {
+    metrics()
+        .rpc_errors
+        .with_label_values(&[endpoint, kind])
+        .inc();
+}
+
+/// reqwest errors end with the URL they were sent to, API key included.
+/// Drop it before the error is logged, shown on /health or returned.
+fn without_url(error: TransportError) -> TransportError {
+    match error {
+        RpcError::Transport(TransportErrorKind::Custom(e)) => {
+            let e = match e.downcast::<reqwest::Error>() {
+                Ok(e) => Box::new(e.without_url()),
+                Err(e) => e,
+            };
+            RpcError::Transport(TransportErrorKind::Custom(e))
+        }
+        other => other,
+    }
+}
+
+/// Coarse class of a failed request, for the error metrics.
+fn error_kind(error: &TransportError) -> &'static str {
+    match error {
+        RpcError::Transport(TransportErrorKind::HttpError(e)) if e.status == 429 => "rate_limited",
+        RpcError::Transport(TransportErrorKind::HttpError(_)) => "http",
+        RpcError::Transport(TransportErrorKind::Custom(_)) => "connection",
+        RpcError::Transport(_) => "transport",
+        RpcError::SerError(_) | RpcError::DeserError { .. } => "decode",
+        _ => "other",
+    }
+}
+
+/// HTTP 429 comes back as a transport error, but some providers report rate
+/// limiting as a JSON-RPC error instead.
+fn is_rate_limited(response: &ResponsePacket) -> bool {
+    response.iter_errors().any(|error| error.code == 429)
+}
+
+/// Cross-checks answers from the two best endpoints.
+struct Quorum {
+    /// Head heights this far apart are normal propagation lag, not a
+    /// disagreement
+    block_tolerance: u64,
+    disagreements: AtomicU64,
+    last_disagreement: Mutex<Option<String>>,
+}
+
+/// An endpoint's name and the provider pinned to it.
+type QuorumMember<'a> = (&'a str, &'a Arc<dyn Provider>);
+
+/// A set of RPC endpoints behind one failover provider, with optional quorum
+/// checks.
+pub struct RpcPool {
+    transport: FailoverTransport,
+    provider: Arc<dyn Provider>,
+    /// One provider per endpoint, in configuration order
+    pinned: Vec<Arc<dyn Provider>>,
+    quorum: Option<Quorum>,
+}
+
+impl RpcPool {
+    pub fn new(urls: &[String], quorum: bool, block_tolerance: u64) -> Result<Self> {
+        if urls.is_empty() {
+            bail!("no RPC endpoints configured");
+        }
+
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                let url: alloy::transports::http::reqwest::Url = url
+                    .parse()
+                    .wrap_err_with(|| format!("invalid RPC URL {:?}", redact(url)))?;
+                let host = url.host_str().unwrap_or("unknown");
+                let name = match url.port() {
+                    Some(port) => format!("{}:{}", host, port),
+                    None => host.to_string(),
+                };
+                Ok(Endpoint {
+                    name,
+                    transport: Http::new(url),
+                    health: Mutex::new(EndpointHealth {
+                        score: 1.0,
+                        latency_ms: 0.0,
+                        consecutive_failures: 0,
+                        benched_until: None,
+                        requests: 0,
+                        failures: 0,
+                        last_error: None,
+                    }),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let transport = FailoverTransport {
+            endpoints: Arc::new(endpoints),
+            active: Arc::new(AtomicUsize::new(0)),
+            pinned: None,
+        };
+        let provider = Self::provider_for(transport.clone());
+        let pinned = (0..urls.len())
+            .map(|index| {
+                Self::provider_for(FailoverTransport {
+                    pinned: Some(index),
+                    ..transport.clone()
+                })
+            })
+            .collect();
+
+        if quorum && urls.len() < 2 {
+            warn!("RPC quorum mode needs at least two endpoints; disabling it");
+        }
+        let quorum = (quorum && urls.len() >= 2).then(|| Quorum {
+            block_tolerance,
+            disagreements: AtomicU64::new(0),
+            last_disagreement: Mutex::new(None),
+        });
+
+        Ok(Self {
+            transport,
+            provider,
+            pinned,
+            quorum,
+        })
+    }
+
+    fn provider_for(transport: FailoverTransport) -> Arc<dyn Provider> {
+        Arc::new(
+            ProviderBuilder::new()
+                .on_client(RpcClient::new(transport, false))
+                .boxed(),
+        )
+    }
+
+    /// Provider that fails over between all endpoints.
+    pub fn provider(&self) -> Arc<dyn Provider> {
+        self.provider.clone()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.transport
+            .endpoints
+            .iter()
+            .map(|endpoint| endpoint.name.as_str())
+            .collect()
+    }
+
+    pub fn quorum_enabled(&self) -> bool {
+        self.quorum.is_some()
+    }
+
+    /// Chain id served by the pool. Every endpoint that answers has to agree.
+    pub async fn chain_id(&self) -> Result<u64> {
+        let mut chain_id = None;
+        for (endpoint, provider) in self.transport.endpoints.iter().zip(&self.pinned) {
+            match provider.get_chain_id().await {
+                Ok(id) => match chain_id {
+                    None => chain_id = Some(id),
+                    Some(expected) if expected != id => bail!(
+                        "RPC endpoint {} serves chain {}, but others serve chain {}",
+                        endpoint.name,
+                        id,
+                        expected
+                    ),
+                    Some(_) => {}
+                },
+                Err(e) => warn!(
+                    "Could not read chain id from RPC endpoint {}: {}",
+                    endpoint.name, e
+                ),
+            }
+        }
+        chain_id.ok_or_else(|| eyre::eyre!("no RPC endpoint answered eth_chainId"))
+    }
+
+    /// The two best-ranked endpoints, when quorum mode is on.
+    fn quorum_pair(&self) -> Option<(&Quorum, [QuorumMember<'_>; 2])> {
+        let quorum = self.quorum.as_ref()?;
+        let ranked = self.transport.ranked();
+        let pick = |index: usize| {
+            (
+                self.transport.endpoints[index].name.as_str(),
+                &self.pinned[index],
+            )
+        };
+        Some((quorum, [pick(ranked[0]), pick(ranked[1])]))
+    }
+
+    fn flag(&self, quorum: &Quorum, disagreement: String) {
+        warn!("RPC quorum disagreement: {}", disagreement);
+        quorum.disagreements.fetch_add(1, Ordering::Relaxed);
+        *quorum
+            .last_disagreement
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(disagreement);
+    }
+
+    /// Current head. In quorum mode, asks two endpoints and takes the lower
+    /// height so nothing is processed before both have it.
+    pub async fn block_number(&self) -> Result<u64> {
+        let Some((quorum, [(a_name, a), (b_name, b)])) = self.quorum_pair() else {
+            return Ok(self.provider.get_block_number().await?);
+        };
+
+        match tokio::join!(a.get_block_number(), b.get_block_number()) {
+            (Ok(a_head), Ok(b_head)) => {
+                if a_head.abs_diff(b_head) > quorum.block_tolerance {
+                    self.flag(
+                        quorum,
+                        format!(
+                            "head block: {} says {}, {} says {}",
+                            a_name, a_head, b_name, b_head
+                        ),
+                    );
+                }
+                Ok(a_head.min(b_head))
+            }
+            (Ok(head), Err(e)) | (Err(e), Ok(head)) => {
+                warn!("RPC quorum unavailable for head block: {}", e);
+                Ok(head)
+            }
+            (Err(_), Err(_)) => Ok(self.provider.get_block_number().await?),
+        }
+    }
+
+    /// Logs matching `filter`. In quorum mode, asks two endpoints and fails
+    /// if they return a different number of logs, so the block gets retried
+    /// rather than published with transfers missing.
+    pub async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>> {
+        let Some((quorum, [(a_name, a), (b_name, b)])) = self.quorum_pair() else {
+            return Ok(self.provider.get_logs(filter).await?);
+        };
+
+        match tokio::join!(a.get_logs(filter), b.get_logs(filter)) {
+            (Ok(a_logs), Ok(b_logs)) => {
+                if a_logs.len() != b_logs.len() {
+                    let disagreement = format!(
+                        "log count: {} returned {}, {} returned {}",
+                        a_name,
+                        a_logs.len(),
+                        b_name,
+                        b_logs.len()
+                    );
+                    self.flag(quorum, disagreement.clone());
+                    bail!("RPC endpoints disagree on {}", disagreement);
+                }
+                Ok(a_logs)
+            }
+            (Ok(logs), Err(e)) | (Err(e), Ok(logs)) => {
+                warn!("RPC quorum unavailable for logs: {}", e);
+                Ok(logs)
+            }
+            (Err(_), Err(_)) => Ok(self.provider.get_logs(filter).await?),
+        }
+    }
+
+    /// Whether any endpoint is answering, i.e. isn't failing repeatedly.
+    pub fn reachable(&self) -> bool {
+        self.transport
+            .endpoints
+            .iter()
+            .any(|endpoint| endpoint.health().consecutive_failures < FAILURE_THRESHOLD)
+    }
+
+    /// Per-endpoint health and quorum state, for the health endpoint.
+    pub fn status(&self) -> serde_json::Value {
+        let active = self.transport.active.load(Ordering::Relaxed);
+        let endpoints: Vec<_> = self
+            .transport
+            .endpoints
+            .iter()
+            .enumerate()
+            .map(|(index, endpoint)| {
+                let benched = endpoint.benched_until().is_some();
+                let health = endpoint.health();
+                serde_json::json!({
+                    "name": endpoint.name,
+                    "active": index == active,
+                    "benched": benched,
+                    "score": (health.score * 100.0).round() / 100.0,
+                    "latency_ms": health.latency_ms.round(),
+                    "requests": health.requests,
+                    "failures": health.failures,
+                    "last_error": health.last_error,
+                })
+            })
+            .collect();
+
+        let quorum = self.quorum.as_ref().map(|quorum| {
+            serde_json::json!({
+                "disagreements": quorum.disagreements.load(Ordering::Relaxed),
+                "last_disagreement": *quorum
+                    .last_disagreement
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner()),
+            })
+        });
+
+        serde_json::json!({ "endpoints": endpoints, "quorum": quorum })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::{http::StatusCode, Filter as _};
+
+    /// A JSON-RPC node on a local port answering `eth_blockNumber` with
+    /// `head` and `eth_getLogs` with `logs` logs, or every request with
+    /// `status` when set.
+    fn node(head: u64, logs: usize, status: Option<u16>) -> String {
+        let route = warp::post()
+            .and(warp::body::json())
+            .map(move |request: serde_json::Value| {
+                if let Some(status) = status {
+                    return warp::reply::with_status(
+                        warp::reply::json(&"unavailable"),
+                        StatusCode::from_u16(status).unwrap(),
+                    );
+                }
+                let result = match request["method"].as_str() {
+                    Some("eth_blockNumber") => serde_json::json!(format!("{:#x}", head)),
+                    Some("eth_getLogs") => serde_json::json!(vec![log(); logs]),
+                    _ => serde_json::Value::Null,
+                };
+                warp::reply::with_status(
+                    warp::reply::json(&serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": request["id"],
+                        "result": result,
+                    })),
+                    StatusCode::OK,
+                )
+            });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{}/v2/node-api-key", addr)
+    }
+
+    fn log() -> serde_json::Value {
+        serde_json::json!({
+            "address": format!("{:#x}", alloy::primitives::Address::ZERO),
+            "topics": [],
+            "data": "0x",
+            "blockHash": format!("{:#x}", alloy::primitives::B256::ZERO),
+            "blockNumber": "0x1",
+            "transactionHash": format!("{:#x}", alloy::primitives::B256::ZERO),
+            "transactionIndex": "0x0",
+            "logIndex": "0x0",
+            "removed": false,
+        })
+    }
+
+    /// Nothing listens here, so requests fail to connect.
+    const DEAD: &str = "http://127.0.0.1:1/v2/dead-api-key";
+
+    fn pool(urls: &[String]) -> RpcPool {
+        RpcPool::new(urls, false, 0).unwrap()
+    }
+
+    fn endpoint(pool: &RpcPool, index: usize) -> &Endpoint {
+        &pool.transport.endpoints[index]
+    }
+
+    #[test]
+    fn endpoints_are_named_by_host_and_port() {
+        let pool = pool(&[
+            "https://base-mainnet.g.alchemy.com/v2/api-key".to_string(),
+            "http://127.0.0.1:8545".to_string(),
+        ]);
+        assert_eq!(
+            pool.names(),
+            ["base-mainnet.g.alchemy.com", "127.0.0.1:8545"]
+        );
+    }
+
+    #[test]
+    fn faster_more_reliable_endpoints_rank_first() {
+        let urls: Vec<_> = (1..=3)
+            .map(|port| format!("http://127.0.0.1:{}", port))
+            .collect();
+        let pool = pool(&urls);
+        endpoint(&pool, 0).succeeded(Duration::from_millis(300));
+        endpoint(&pool, 1).succeeded(Duration::from_millis(90));
+        endpoint(&pool, 2).succeeded(Duration::from_millis(100));
+        assert_eq!(pool.transport.ranked(), [1, 2, 0]);
+
+        // Failures inflate an otherwise fast endpoint's rank
+        endpoint(&pool, 1).failed("timeout".to_string());
+        endpoint(&pool, 1).failed("timeout".to_string());
+        assert_eq!(pool.transport.ranked(), [2, 1, 0]);
+    }
+
+    #[test]
+    fn failing_endpoints_are_benched_until_they_recover() {
+        let urls: Vec<_> = (1..=2)
+            .map(|port| format!("http://127.0.0.1:{}", port))
+            .collect();
+        let pool = pool(&urls);
+        for _ in 0..FAILURE_THRESHOLD - 1 {
+            endpoint(&pool, 0).failed("boom".to_string());
+        }
+        assert!(endpoint(&pool, 0).benched_until().is_none());
+
+        endpoint(&pool, 0).failed("boom".to_string());
+        let benched_until = endpoint(&pool, 0).benched_until().unwrap();
+        assert!(benched_until > Instant::now() + BASE_COOLDOWN - Duration::from_secs(1));
+        // Still tried, but only as a last resort
+        assert_eq!(pool.transport.ranked(), [1, 0]);
+        assert!(pool.reachable());
+
+        for _ in 0..FAILURE_THRESHOLD {
+            endpoint(&pool, 1).failed("boom".to_string());
+        }
+        assert!(!pool.reachable());
+
+        endpoint(&pool, 0).succeeded(Duration::from_millis(10));
+        assert!(endpoint(&pool, 0).benched_until().is_none());
+        assert_eq!(pool.transport.ranked(), [0, 1]);
+        assert!(pool.reachable());
+    }
+
+    #[tokio::test]
+    async fn requests_fail_over_to_the_next_endpoint() {
+        let pool = pool(&[
+            DEAD.to_string(),
+            node(100, 0, Some(429)),
+            node(120, 0, None),
+        ]);
+
+        assert_eq!(pool.block_number().await.unwrap(), 120);
+        assert_eq!(endpoint(&pool, 0).health().failures, 1);
+        assert_eq!(endpoint(&pool, 1).health().failures, 1);
+        let status = pool.status();
+        assert_eq!(status["endpoints"][2]["active"], true);
+        assert_eq!(status["endpoints"][2]["failures"], 0);
+    }
+
+    #[tokio::test]
+    async fn errors_never_include_the_url() {
+        let pool = pool(&[DEAD.to_string()]);
+
+        let err = pool.block_number().await.unwrap_err();
+        assert!(!format!("{:#}", err).contains("api-key"), "{:#}", err);
+        let last_error = pool.status()["endpoints"][0]["last_error"].to_string();
+        assert!(
+            last_error.contains("error sending request"),
+            "{}",
+            last_error
+        );
+        assert!(!last_error.contains("api-key"), "{}", last_error);
+    }
+
+    #[tokio::test]
+    async fn quorum_takes_the_lower_head_and_flags_large_gaps() {
+        let pool = RpcPool::new(&[node(100, 0, None), node(101, 0, None)], true, 2).unwrap();
+        assert_eq!(pool.block_number().await.unwrap(), 100);
+        assert_eq!(pool.status()["quorum"]["disagreements"], 0);
+
+        let pool = RpcPool::new(&[node(100, 0, None), node(120, 0, None)], true, 2).unwrap();
+        assert_eq!(pool.block_number().await.unwrap(), 100);
+        assert_eq!(pool.status()["quorum"]["disagreements"], 1);
+    }
+
+    #[tokio::test]
+    async fn quorum_rejects_differing_log_counts() {
+        let pool = RpcPool::new(&[node(100, 2, None), node(100, 2, None)], true, 0).unwrap();
+        assert_eq!(pool.get_logs(&Filter::new()).await.unwrap().len(), 2);
+
+        let pool = RpcPool::new(&[node(100, 2, None), node(100, 1, None)], true, 0).unwrap();
+        let err = pool.get_logs(&Filter::new()).await.unwrap_err();
+        assert!(err.to_string().contains("disagree on log count"), "{}", err);
+        let status = pool.status();
+        assert_eq!(status["quorum"]["disagreements"], 1);
+        assert!(status["quorum"]["last_disagreement"]
+            .as_str()
+            .unwrap()
+            .starts_with("log count"));
+    }
+
+    #[tokio::test]
+    async fn quorum_falls_back_to_one_endpoint_when_the_other_is_down() {
+        let pool = RpcPool::new(&[DEAD.to_string(), node(100, 1, None)], true, 0).unwrap();
+        assert_eq!(pool.block_number().await.unwrap(), 100);
+        assert_eq!(pool.get_logs(&Filter::new()).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::{metrics::metrics, Probe, StablecoinMonitor, StreamEvent};
+use eyre::{Result, WrapErr};
+use futures_util::{SinkExt, StreamExt};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::{broadcast, watch, Notify};
+use tracing::{error, info, warn};
+use warp::{
+    http::StatusCode,
+    ws::{Message, WebSocket, Ws},
+    Filter, Reply,
+};
+
+type Monitors = Arc<Vec<Arc<StablecoinMonitor>>>;
+
+/// Close code for "service restart": the client should reconnect
+const RESTART_CLOSE_CODE: u16 = 1012;
+
+/// Serve everything on `port`:
+///
+/// - `/ws` (or `/`): WebSocket stream of published events
+/// - `/health` (or `/`): progress and gaps as JSON
+/// - `/health/live` and `/health/ready`: checks as JSON, 503 when failing
+/// - `/metrics`: Prometheus text format
+///
+/// New connections are refused once `stopping` is set. Once `closing` is
+/// set, WebSocket clients are sent anything still buffered for them and a
+/// close frame asking them to reconnect; the server returns once every
+/// client's connection has been closed or dropped.
+pub async fn serve(
+    port: u16,
+    tx_broadcaster: broadcast::Sender<StreamEvent>,
+    monitors: Vec<Arc<StablecoinMonitor>>,
+    mut stopping: watch::Receiver<bool>,
+    closing: watch::Receiver<bool>,
+) -> Result<()> {
+    let monitors: Monitors = Arc::new(monitors);
+    let with_monitors = warp::any().map(move || monitors.clone());
+    let connections = Connections::default();
+
+    let websocket = warp::path::end()
+        .or(warp::path!("ws"))
+        .unify()
+        .and(warp::ws())
+        .and(warp::addr::remote())
+        .map({
+            let connections = connections.clone();
+            move |ws: Ws, addr: Option<SocketAddr>| {
+                let rx = tx_broadcaster.subscribe();
+                let closing = closing.clone();
+                let peer = addr.map_or_else(|| "unknown".to_string(), |addr| addr.to_string());
+                let connection = connections.open();
+                ws.on_upgrade(move |socket| async move {
+                    if let Err(e) = handle_websocket(socket, &peer, rx, closing).await {
+                        error!("WebSocket error for {}: {}", peer, e);
+                    }
+                    drop(connection);
+                })
+            }
+        });
+
+    let health = warp::path::end()
+        .or(warp::path!("health"))
+        .unify()
+        .and(with_monitors.clone())
+        .then(|monitors: Monitors| async move {
+            let mut chains = Vec::with_capacity(monitors.len());
+            for monitor in monitors.iter() {
+                chains.push(monitor.health().await);
+            }
+            let degraded = chains.iter().any(|chain| chain["status"] != "ok");
+            warp::reply::json(&serde_json::json!({
+                "status": if degraded { "degraded" } else { "ok" },
+                "chains": chains,
+            }))
+        });
+
+    let live = warp::path!("health" / "live")
+        .and(with_monitors.clone())
+        .then(|monitors| checks(monitors, |probe| probe.live));
+    let ready = warp::path!("health" / "ready")
+        .and(with_monitors)
+        .then(|monitors| checks(monitors, |probe| probe.ready));
+
+    let metrics = warp::path!("metrics").map(|| {
+        warp::reply::with_header(
+            metrics().render(),
+            "Content-Type",
+            "text/plain; version=0.0.4",
+        )
+    });
+
+    let routes = websocket.or(warp::get().and(health.or(live).or(ready).or(metrics)));
+
+    let (addr, server) = warp::serve(routes)
+        .try_bind_with_graceful_shutdown(([0, 0, 0, 0], port), async move {
+            let _ = stopping.wait_for(|stopping| *stopping).await;
+        })
+        .wrap_err_with(|| format!("failed to listen on port {}", port))?;
+    info!(
+        "Listening on {} (WebSocket on /ws, health on /health, metrics on /metrics)",
+        addr
+    );
+    server.await;
+
+    // Upgraded connections outlive warp's graceful shutdown
+    connections.closed().await;
+    Ok(())
+}
+
+/// Open WebSocket connections, counted so shutdown can wait for their close
+/// frames to go out.
+///
+/// game-server's `main.rs` has a copy; keep the two in sync.
+#[derive(Clone, Default)]
+struct Connections {
+    shared: Arc<ConnectionCount>,
+}
+
+#[derive(Default)]
+struct ConnectionCount {
+    open: AtomicUsize,
+    closed: Notify,
+}
+
+/// Counts as an open connection until dropped.
+struct Connection(Connections);
+
+impl Connections {
+    fn open(&self) -> Connection {
+        self.shared.open.fetch_add(1, Ordering::SeqCst);
+        Connection(self.clone())
+    }
+
+    /// Resolves once no connections are open.
+    async fn closed(&self) {
+        loop {
+            let closed = self.shared.closed.notified();
+            if self.shared.open.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            closed.await;
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if self.0.shared.open.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.shared.closed.notify_waiters();
+        }
+    }
+}
+
+/// `/health/live` or `/health/ready`: every chain's checks, and 503 unless
+/// `passed` holds for all of them.
+async fn checks(monitors: Monitors, passed: fn(&Probe) -> bool) -> impl Reply {
+    let mut ok = true;
+    let mut chains = Vec::with_capacity(monitors.len());
+    for monitor in monitors.iter() {
+        let mut probe = monitor.probe().await;
+        let chain_ok = passed(&probe);
+        probe.body["status"] = if chain_ok { "ok" } else { "failing" }.into();
+        ok &= chain_ok;
+        chains.push(probe.body);
+    }
+
+    let body = serde_json::json!({
+        "status": if ok { "ok" } else { "failing" },
+        "chains": chains,
+    });
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    warp::reply::with_status(warp::reply::json(&body), status)
+}
+
+async fn handle_websocket(
+    socket: WebSocket,
+    peer: &str,
+    mut rx: broadcast::Receiver<StreamEvent>,
+    mut closing: watch::Receiver<bool>,
+) -> Result<()> {
+    info!("New WebSocket connection from: {}", peer);
+
+    let (mut ws_sender, mut ws_receiver) = socket.split();
+
+    metrics().websocket_clients.inc();
+    let result: Result<()> = async {
+        loop {
+            tokio::select! {
+                // Forward transaction data to client
+                event = rx.recv() => {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("WebSocket client {} fell behind and missed {} events", peer, skipped);
+                            metrics().broadcast_lagged.inc_by(skipped);
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+                    let json = serde_json::to_string(&event)?;
+                    if ws_sender.send(Message::text(json)).await.is_err() {
+                        break;
+                    }
+                }
+
+                // Shutting down: flush what's buffered and ask the client to
+                // reconnect
+                _ = async {
+                    let _ = closing.wait_for(|closing| *closing).await;
+                } => {
+                    while let Ok(event) = rx.try_recv() {
+                        let json = serde_json::to_string(&event)?;
+                        if ws_sender.send(Message::text(json)).await.is_err() {
+                            break;
+                        }
+                    }
+                    let close = Message::close_with(RESTART_CLOSE_CODE, "server restarting, reconnect");
+                    let _ = ws_sender.send(close).await;
+                    break;
+                }
+
+                // Handle incoming messages from client
+                Some(msg) = ws_receiver.next() => {
+                    match msg {
+                        Ok(msg) if msg.is_ping() => {
+                            let _ = ws_sender.send(Message::pong(msg.into_bytes())).await;
+                        }
+                        Ok(msg) if !msg.is_close() => {}
+                        _ => {
+                            info!("Client {} disconnected", peer);
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    .await;
+    metrics().websocket_clients.dec();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionData;
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+    #[tokio::test]
+    async fn closing_flushes_events_then_asks_clients_to_reconnect() {
+        let (tx_broadcaster, _) = broadcast::channel(16);
+        let (close_clients, closing) = watch::channel(false);
+        let connections = Connections::default();
+        let route = warp::ws().map({
+            let tx_broadcaster = tx_broadcaster.clone();
+            let connections = connections.clone();
+            move |ws: Ws| {
+                let rx = tx_broadcaster.subscribe();
+                let closing = closing.clone();
+                let connection = connections.open();
+                ws.on_upgrade(move |socket| async move {
+                    let _ = handle_websocket(socket, "test", rx, closing).await;
+                    drop(connection);
+                })
+            }
+        });
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr))
+            .await
+            .unwrap();
+
+        // Subscribed once the upgrade has gone through
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while tx_broadcaster.receiver_count() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        tx_broadcaster
+            .send(StreamEvent::Transfer(TransactionData::example(7, 0)))
+            .unwrap();
+        close_clients.send(true).unwrap();
+
+        let Some(Ok(ClientMessage::Text(text))) = client.next().await else {
+            panic!("expected the buffered event");
+        };
+        assert!(text.contains("\"block_number\":7"));
+        let Some(Ok(ClientMessage::Close(Some(frame)))) = client.next().await else {
+            panic!("expected a close frame");
+        };
+        assert_eq!(u16::from(frame.code), RESTART_CLOSE_CODE);
+        assert_eq!(frame.reason, "server restarting, reconnect");
+
+        tokio::time::timeout(Duration::from_secs(5), connections.closed())
+            .await
+            .unwrap();
+    }
+}
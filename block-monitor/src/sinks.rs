@@ -0,0 +1,1150 @@
+use crate::{
+    chains::ChainConfig,
+    config::Secret,
+    metrics::metrics,
+    outbox::{self, Outbox},
+    redis_conn::RedisConnection,
+    StreamEvent,
+};
+use eyre::{bail, Result, WrapErr};
+use futures_util::future::BoxFuture;
+use redis::aio::MultiplexedConnection;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{broadcast, mpsc, oneshot, Notify},
+    task::JoinHandle,
+};
+use tracing::{error, info, warn, Instrument};
+
+const DEFAULT_STREAM_KEY: &str = "stablecoin:transactions";
+const DEFAULT_SUPPLY_STREAM_KEY: &str = "stablecoin:supply";
+const DEFAULT_STREAM_MAXLEN: u64 = 10_000;
+
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Somewhere published events go.
+pub trait Sink: Send + Sync {
+    fn send<'a>(&'a self, event: &'a StreamEvent) -> BoxFuture<'a, Result<()>>;
+
+    /// Sink-specific details for the health endpoint.
+    fn status(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Push out anything buffered before the process exits.
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    /// Whether the backend is reachable, for sinks that hold a connection.
+    fn connected(&self) -> Option<bool> {
+        None
+    }
+
+    /// Whether the sink takes `event`. Supply updates only go to Redis, on a
+    /// stream of their own.
+    fn accepts(&self, event: &StreamEvent) -> bool {
+        !matches!(event, StreamEvent::Supply(_))
+    }
+}
+
+/// Redis Streams, with the event's JSON under `data` next to flat fields.
+///
+/// While Redis is unreachable, entries go to an on-disk [`Outbox`] instead.
+/// A background task drains it in order once the shared connection is back;
+/// until it's empty, new entries queue behind it so the stream never goes
+/// out of order.
+pub struct RedisSink {
+    chain: String,
+    conn: RedisConnection,
+    stream: StreamConfig,
+    outbox: tokio::sync::Mutex<Outbox>,
+    buffered: AtomicUsize,
+}
+
+impl RedisSink {
+    /// Drain anything left in the outbox and start the drain loop, which
+    /// checks every `retry_interval`. Redis being unreachable now isn't an
+    /// error; events are buffered in the outbox until it's back.
+    async fn start(
+        chain: &str,
+        conn: RedisConnection,
+        stream: StreamConfig,
+        outbox: Outbox,
+        retry_interval: Duration,
+    ) -> Arc<Self> {
+        if !outbox.is_empty() {
+            info!(
+                "{} events waiting in outbox {}",
+                outbox.len(),
+                outbox.path().display()
+            );
+        }
+
+        metrics()
+            .redis_outbox
+            .with_label_values(&[chain])
+            .set(outbox.len() as i64);
+        let sink = Arc::new(Self {
+            chain: chain.to_string(),
+            conn,
+            stream,
+            buffered: AtomicUsize::new(outbox.len()),
+            outbox: tokio::sync::Mutex::new(outbox),
+        });
+        sink.drain().await;
+
+        let weak = Arc::downgrade(&sink);
+        tokio::spawn(Self::drain_loop(weak, retry_interval).in_current_span());
+        sink
+    }
+
+    async fn drain_loop(sink: Weak<Self>, retry_interval: Duration) {
+        loop {
+            tokio::time::sleep(retry_interval).await;
+            let Some(sink) = sink.upgrade() else {
+                return;
+            };
+            sink.drain().await;
+        }
+    }
+
+    /// Write buffered entries to Redis, oldest first, stopping at the first
+    /// failure.
+    async fn drain(&self) {
+        let mut outbox = self.outbox.lock().await;
+        if let Err(e) = self.drain_outbox(&mut outbox).await {
+            error!("Failed to drain outbox: {:#}", e);
+        }
+    }
+
+    async fn drain_outbox(&self, outbox: &mut Outbox) -> Result<()> {
+        if outbox.is_empty() {
+            return Ok(());
+        }
+        let Some(mut conn) = self.conn.get() else {
+            return Ok(());
+        };
+
+        let entries = outbox.entries().await?;
+        let mut delivered = 0;
+        for entry in &entries {
+            if let Err(e) = self.stream.write(&mut conn, entry).await {
+                warn!("Redis sink failed while draining outbox: {:#}", e);
+                self.record_failure();
+                break;
+            }
+            delivered += 1;
+        }
+
+        outbox.remove_front(delivered).await?;
+        self.set_buffered(outbox.len());
+        info!(
+            "Drained {} events from outbox; {} left",
+            delivered,
+            outbox.len()
+        );
+        Ok(())
+    }
+
+    fn set_buffered(&self, len: usize) {
+        self.buffered.store(len, Ordering::Relaxed);
+        metrics()
+            .redis_outbox
+            .with_label_values(&[&self.chain])
+            .set(len as i64);
+    }
+
+    fn record_failure(&self) {
+        metrics()
+            .redis_failures
+            .with_label_values(&[&self.chain])
+            .inc();
+    }
+}
+
+impl Sink for RedisSink {
+    fn send<'a>(&'a self, event: &'a StreamEvent) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut entry = vec![
+                ("type".to_string(), event.kind().to_string()),
+                ("data".to_string(), serde_json::to_string(event)?),
+            ];
+            entry.extend(
+                event
+                    .fields()
+                    .into_iter()
+                    .map(|(field, value)| (field.to_string(), value)),
+            );
+
+            let mut outbox = self.outbox.lock().await;
+            if outbox.is_empty() {
+                if let Some(mut conn) = self.conn.get() {
+                    match self.stream.write(&mut conn, &entry).await {
+                        Ok(()) => return Ok(()),
+                        Err(e) => {
+                            warn!("Redis unavailable, buffering events in outbox: {:#}", e);
+                            self.record_failure();
+                        }
+                    }
+                }
+            }
+
+            outbox.push(&entry).await?;
+            self.set_buffered(outbox.len());
+            Ok(())
+        })
+    }
+
+    fn status(&self) -> Option<serde_json::Value> {
+        Some(serde_json::json!({
+            "connected": self.conn.get().is_some(),
+            "outbox": self.buffered.load(Ordering::Relaxed),
+        }))
+    }
+
+    fn connected(&self) -> Option<bool> {
+        Some(self.conn.get().is_some())
+    }
+
+    fn accepts(&self, _event: &StreamEvent) -> bool {
+        true
+    }
+
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            self.drain().await;
+            let outbox = self.outbox.lock().await;
+            if !outbox.is_empty() {
+                warn!(
+                    "{} events left in outbox {}; they'll be sent on the next run",
+                    outbox.len(),
+                    outbox.path().display()
+                );
+            }
+        })
+    }
+}
+
+/// How old entries are trimmed from the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trim {
+    /// Keep roughly this many entries
+    MaxLen(u64),
+    /// Drop entries older than this, by their ID's timestamp
+    MaxAge(Duration),
+    /// Keep everything
+    None,
+}
+
+impl fmt::Display for Trim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MaxLen(maxlen) => write!(f, "about {} entries", maxlen),
+            Self::MaxAge(age) => write!(f, "entries from the last {:?}", age),
+            Self::None => f.write_str("everything"),
+        }
+    }
+}
+
+/// Which Redis streams entries go to.
+#[derive(Clone)]
+struct StreamConfig {
+    key: String,
+    /// Where supply updates go instead
+    supply_key: String,
+    trim: Trim,
+    /// Also write each entry to `<key>:<SYMBOL>`
+    per_token: bool,
+}
+
+impl StreamConfig {
+    /// `REDIS_STREAM_KEY`, and `REDIS_SUPPLY_STREAM_KEY` for supply updates,
+    /// trimmed by `REDIS_STREAM_MAXLEN` or `REDIS_STREAM_MAX_AGE_SECS` (0 keeps
+    /// everything), with per-token copies when `REDIS_STREAM_PER_TOKEN` is set.
+    fn from_env(chain: &ChainConfig) -> Result<Self> {
+        let key = chain
+            .var("REDIS_STREAM_KEY")
+            .unwrap_or_else(|| DEFAULT_STREAM_KEY.to_string());
+        let trim = match (
+            chain.var("REDIS_STREAM_MAXLEN"),
+            chain.var("REDIS_STREAM_MAX_AGE_SECS"),
+        ) {
+            (Some(_), Some(_)) => {
+                bail!("set only one of REDIS_STREAM_MAXLEN and REDIS_STREAM_MAX_AGE_SECS")
+            }
+            (None, Some(_)) => match chain.parse("REDIS_STREAM_MAX_AGE_SECS", 0)? {
+                0 => Trim::None,
+                secs => Trim::MaxAge(Duration::from_secs(secs)),
+            },
+            _ => match chain.parse("REDIS_STREAM_MAXLEN", DEFAULT_STREAM_MAXLEN)? {
+                0 => Trim::None,
+                maxlen => Trim::MaxLen(maxlen),
+            },
+        };
+        Ok(Self {
+            key,
+            supply_key: chain
+                .var("REDIS_SUPPLY_STREAM_KEY")
+                .unwrap_or_else(|| DEFAULT_SUPPLY_STREAM_KEY.to_string()),
+            trim,
+            per_token: chain.flag("REDIS_STREAM_PER_TOKEN", false)?,
+        })
+    }
+
+    /// Streams `entry` belongs on: the main one, then its token's, or the
+    /// supply stream alone for supply updates.
+    fn keys(&self, entry: &outbox::Entry) -> Vec<String> {
+        let field = |name: &str| {
+            entry
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+        };
+        if field("type") == Some("supply") {
+            return vec![self.supply_key.clone()];
+        }
+        let mut keys = vec![self.key.clone()];
+        if self.per_token {
+            if let Some(symbol) = field("stablecoin") {
+                keys.push(format!("{}:{}", self.key, symbol));
+            }
+        }
+        keys
+    }
+
+    /// Append an entry to its streams, all or nothing.
+    async fn write(&self, conn: &mut MultiplexedConnection, entry: &outbox::Entry) -> Result<()> {
+        let keys = self.keys(entry);
+        let mut pipe = redis::pipe();
+        if keys.len() > 1 {
+            pipe.atomic();
+        }
+        for key in &keys {
+            let cmd = pipe.cmd("XADD").arg(key);
+            match self.trim {
+                Trim::MaxLen(maxlen) => {
+                    cmd.arg("MAXLEN").arg("~").arg(maxlen);
+                }
+                Trim::MaxAge(age) => {
+                    let cutoff = SystemTime::now()
+                        .checked_sub(age)
+                        .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+                        .unwrap_or_default();
+                    cmd.arg("MINID").arg("~").arg(cutoff.as_millis() as u64);
+                }
+                Trim::None => {}
+            }
+            cmd.arg("*").arg(entry);
+        }
+        pipe.query_async::<()>(conn)
+            .await
+            .wrap_err_with(|| format!("XADD to {} failed", keys.join(", ")))?;
+        Ok(())
+    }
+}
+
+/// The built-in WebSocket server's broadcast channel.
+pub struct WebSocketSink {
+    broadcaster: broadcast::Sender<StreamEvent>,
+}
+
+impl Sink for WebSocketSink {
+    fn send<'a>(&'a self, event: &'a StreamEvent) -> BoxFuture<'a, Result<()>> {
+        // No connected clients isn't an error
+        let _ = self.broadcaster.send(event.clone());
+        metrics().broadcast_queue.set(self.broadcaster.len() as i64);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// One JSON event per line on stdout.
+pub struct StdoutSink;
+
+impl Sink for StdoutSink {
+    fn send<'a>(&'a self, event: &'a StreamEvent) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut line = serde_json::to_vec(event)?;
+            line.push(b'\n');
+            let mut stdout = tokio::io::stdout();
+            stdout.write_all(&line).await?;
+            stdout.flush().await?;
+            Ok(())
+        })
+    }
+}
+
+/// One JSON event per line in a file, rotated to `<path>.1`, `<path>.2`, ...
+/// once it grows past `max_bytes`.
+pub struct FileSink {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: u32,
+    file: tokio::sync::Mutex<Option<(File, u64)>>,
+}
+
+impl FileSink {
+    async fn rotate(&self) -> Result<()> {
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if tokio::fs::try_exists(&from).await.unwrap_or(false) {
+                tokio::fs::rename(&from, self.rotated_path(index + 1)).await?;
+            }
+        }
+        if self.max_files > 0 {
+            tokio::fs::rename(&self.path, self.rotated_path(1)).await?;
+        } else {
+            tokio::fs::remove_file(&self.path).await?;
+        }
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+}
+
+impl Sink for FileSink {
+    fn send<'a>(&'a self, event: &'a StreamEvent) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut line = serde_json::to_vec(event)?;
+            line.push(b'\n');
+
+            let mut file = self.file.lock().await;
+            if let Some((_, written)) = file.as_ref() {
+                if *written + line.len() as u64 > self.max_bytes {
+                    *file = None;
+                    self.rotate()
+                        .await
+                        .wrap_err_with(|| format!("failed to rotate {}", self.path.display()))?;
+                }
+            }
+            if file.is_none() {
+                let opened = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .await
+                    .wrap_err_with(|| format!("failed to open {}", self.path.display()))?;
+                let written = opened.metadata().await?.len();
+                *file = Some((opened, written));
+            }
+
+            let Some((handle, written)) = file.as_mut() else {
+                unreachable!("file was just opened");
+            };
+            if let Err(e) = handle.write_all(&line).await {
+                // Reopen on the next attempt
+                *file = None;
+                return Err(e)
+                    .wrap_err_with(|| format!("failed to write to {}", self.path.display()));
+            }
+            *written += line.len() as u64;
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            if let Some((file, _)) = self.file.lock().await.as_mut() {
+                if let Err(e) = file.flush().await {
+                    error!("Failed to flush {}: {}", self.path.display(), e);
+                }
+            }
+        })
+    }
+}
+
+/// HTTP POST of each event's JSON.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    authorization: Option<String>,
+}
+
+impl Sink for WebhookSink {
+    fn send<'a>(&'a self, event: &'a StreamEvent) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut request = self.client.post(&self.url).json(event);
+            if let Some(authorization) = &self.authorization {
+                request = request.header(reqwest::header::AUTHORIZATION, authorization);
+            }
+            // reqwest errors end with the URL, which may hold a token;
+            // they're logged and shown on /health
+            request
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(reqwest::Error::without_url)?;
+            Ok(())
+        })
+    }
+}
+
+/// What to do when a sink's queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OnFull {
+    /// Wait for room, slowing block processing down to the sink's pace
+    Block,
+    /// Drop the event and count it
+    Drop,
+}
+
+/// Every sink there is, by the name `SINKS` and `--sink` use.
+const SINK_NAMES: [&str; 5] = ["redis", "websocket", "stdout", "file", "webhook"];
+
+/// Sink names from a comma-separated list, as for `SINKS`.
+pub fn parse_names(names: &str) -> Result<Vec<&'static str>> {
+    let mut parsed = Vec::new();
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let Some(known) = SINK_NAMES
+            .into_iter()
+            .find(|known| known.eq_ignore_ascii_case(name))
+        else {
+            bail!(
+                "unknown sink {:?}; expected redis, websocket, stdout, file or webhook",
+                name
+            );
+        };
+        if parsed.contains(&known) {
+            bail!("sink {} is listed twice", known);
+        }
+        parsed.push(known);
+    }
+    Ok(parsed)
+}
+
+/// A sink's queue: `SINK_<NAME>_QUEUE_SIZE`, `SINK_<NAME>_ON_FULL` (block or
+/// drop) and `SINK_<NAME>_MAX_RETRIES`.
+#[derive(Debug, Clone, Copy)]
+struct QueueSettings {
+    size: usize,
+    on_full: OnFull,
+    max_retries: u32,
+}
+
+impl QueueSettings {
+    fn from_env(chain: &ChainConfig, name: &str) -> Result<Self> {
+        let prefix = format!("SINK_{}", name.to_ascii_uppercase());
+        let on_full = match chain.var(&format!("{}_ON_FULL", prefix)).as_deref() {
+            Some("drop") => OnFull::Drop,
+            Some("block") | None => OnFull::Block,
+            Some(other) => bail!(
+                "invalid {}_ON_FULL {:?}; expected block or drop",
+                prefix,
+                other
+            ),
+        };
+        Ok(Self {
+            size: chain.parse(&format!("{}_QUEUE_SIZE", prefix), 1000)?,
+            on_full,
+            max_retries: chain.parse(&format!("{}_MAX_RETRIES", prefix), 5)?,
+        })
+    }
+}
+
+/// Sink settings for one chain, read and checked at startup.
+pub struct SinkSettings {
+    /// Sinks named in `SINKS`; defaults to redis, when `REDIS_URL` is set,
+    /// and websocket
+    pub names: Vec<&'static str>,
+    stream: StreamConfig,
+    outbox_path: PathBuf,
+    outbox_retry: Duration,
+    file_path: PathBuf,
+    file_max_bytes: u64,
+    file_max_files: u32,
+    webhook_url: Option<Secret>,
+    webhook_authorization: Option<Secret>,
+    webhook_timeout: Duration,
+    queues: Vec<(&'static str, QueueSettings)>,
+}
+
+impl SinkSettings {
+    pub fn load(chain: &ChainConfig, redis: bool) -> Result<Self> {
+        let names = match chain.var("SINKS") {
+            Some(names) => parse_names(&names)?,
+            None if redis => vec!["redis", "websocket"],
+            None => vec!["websocket"],
+        };
+        let settings = Self {
+            stream: StreamConfig::from_env(chain)?,
+            outbox_path: chain
+                .var("OUTBOX_PATH")
+                .unwrap_or_else(|| format!("outbox-{}.ndjson", chain.name))
+                .into(),
+            outbox_retry: Duration::from_secs(chain.parse("OUTBOX_RETRY_SECS", 5)?.max(1)),
+            file_path: chain
+                .var("SINK_FILE_PATH")
+                .unwrap_or_else(|| format!("events-{}.ndjson", chain.name))
+                .into(),
+            file_max_bytes: chain.parse("SINK_FILE_MAX_BYTES", 100 * 1024 * 1024)?,
+            file_max_files: chain.parse("SINK_FILE_MAX_FILES", 5)?,
+            webhook_url: chain.var("SINK_WEBHOOK_URL").map(Secret::new),
+            webhook_authorization: chain.var("SINK_WEBHOOK_AUTHORIZATION").map(Secret::new),
+            webhook_timeout: Duration::from_secs(chain.parse("SINK_WEBHOOK_TIMEOUT_SECS", 10)?),
+            queues: SINK_NAMES
+                .into_iter()
+                .map(|name| Ok((name, QueueSettings::from_env(chain, name)?)))
+                .collect::<Result<_>>()?,
+            names,
+        };
+        settings.check(&settings.names, redis)?;
+        Ok(settings)
+    }
+
+    /// Whether `names` can all be set up.
+    fn check(&self, names: &[&str], redis: bool) -> Result<()> {
+        if names.contains(&"redis") && !redis {
+            bail!("redis sink enabled but REDIS_URL is not set");
+        }
+        if names.contains(&"webhook") && self.webhook_url.is_none() {
+            bail!("webhook sink enabled but SINK_WEBHOOK_URL is not set");
+        }
+        Ok(())
+    }
+
+    fn queue(&self, name: &str) -> QueueSettings {
+        self.queues
+            .iter()
+            .find(|(queue, _)| *queue == name)
+            .map(|(_, queue)| *queue)
+            .expect("queue settings for every sink")
+    }
+
+    pub fn log_summary(&self) {
+        info!("    Sinks: {}", self.names.join(", "));
+        for name in &self.names {
+            let queue = self.queue(name);
+            let details = match *name {
+                "redis" => format!(
+                    "streams {}{} and {}, keeping {}; outbox {} drained every {:?}",
+                    self.stream.key,
+                    if self.stream.per_token {
+                        " (and per token)"
+                    } else {
+                        ""
+                    },
+                    self.stream.supply_key,
+                    self.stream.trim,
+                    self.outbox_path.display(),
+                    self.outbox_retry
+                ),
+                "file" => format!(
+                    "{}, rotated at {} bytes, {} kept",
+                    self.file_path.display(),
+                    self.file_max_bytes,
+                    self.file_max_files
+                ),
+                "webhook" => format!(
+                    "{} (authorization {}, timeout {:?})",
+                    self.webhook_url
+                        .as_ref()
+                        .map(Secret::to_string)
+                        .unwrap_or_default(),
+                    self.webhook_authorization
+                        .as_ref()
+                        .map(Secret::to_string)
+                        .unwrap_or_else(|| "-".to_string()),
+                    self.webhook_timeout
+                ),
+                _ => String::new(),
+            };
+            info!(
+                "      {}: {}{}queue {}, {} retries, {} when full",
+                name,
+                details,
+                if details.is_empty() { "" } else { "; " },
+                queue.size,
+                queue.max_retries,
+                match queue.on_full {
+                    OnFull::Block => "block",
+                    OnFull::Drop => "drop",
+                }
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+struct SinkStats {
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+/// An event on its way to every sink. Once each sink has delivered it,
+/// given up on it or dropped it, whether all of them delivered it is sent
+/// on `done`; if it's discarded unfinished, `done` is dropped instead.
+struct Queued {
+    event: StreamEvent,
+    outcome: Mutex<Outcome>,
+}
+
+struct Outcome {
+    remaining: usize,
+    lost: bool,
+    done: Option<oneshot::Sender<bool>>,
+}
+
+impl Queued {
+    /// Record one sink's result.
+    fn finish(&self, delivered: bool) {
+        let mut outcome = self.outcome.lock().unwrap_or_else(|e| e.into_inner());
+        outcome.lost |= !delivered;
+        outcome.remaining = outcome.remaining.saturating_sub(1);
+        if outcome.remaining == 0 {
+            if let Some(done) = outcome.done.take() {
+                let _ = done.send(!outcome.lost);
+            }
+        }
+    }
+}
+
+/// A sink running on its own task behind a bounded queue.
+struct SinkHandle {
+    name: &'static str,
+    sink: Arc<dyn Sink>,
+    queue: mpsc::Sender<Arc<Queued>>,
+    on_full: OnFull,
+    stats: Arc<SinkStats>,
+    /// Tells the worker to stop taking events and finish its queue
+    closing: Arc<Notify>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Sinks for a backfill rather than the running monitor.
+pub struct BackfillSinks<'a> {
+    /// Comma-separated, as for `SINKS`
+    pub names: &'a str,
+    /// The backfill's progress file. Its Redis outbox and file sink output
+    /// are named after it, e.g. `backfill-base-1-2.outbox.ndjson`, so they
+    /// can't mix with the running monitor's.
+    pub progress_file: &'a Path,
+}
+
+/// Every sink enabled for a chain. Each one has its own queue, retries and
+/// backpressure, so a slow webhook can't hold up Redis.
+pub struct Sinks {
+    chain: String,
+    handles: Vec<SinkHandle>,
+}
+
+impl Sinks {
+    /// The sinks in `settings`, or the backfill's.
+    pub async fn new(
+        chain: &str,
+        settings: &SinkSettings,
+        backfill: Option<BackfillSinks<'_>>,
+        redis: Option<&RedisConnection>,
+        broadcaster: broadcast::Sender<StreamEvent>,
+    ) -> Result<Self> {
+        let names = match &backfill {
+            Some(backfill) => {
+                let names = parse_names(backfill.names)?;
+                settings.check(&names, redis.is_some())?;
+                names
+            }
+            None => settings.names.clone(),
+        };
+
+        let mut handles = Vec::with_capacity(names.len());
+        for name in names {
+            let sink: Arc<dyn Sink> = match name {
+                "redis" => {
+                    let Some(redis) = redis else {
+                        bail!("redis sink enabled but REDIS_URL is not set");
+                    };
+                    let outbox = Outbox::open(match &backfill {
+                        Some(backfill) => backfill.progress_file.with_extension("outbox.ndjson"),
+                        None => settings.outbox_path.clone(),
+                    })
+                    .await?;
+                    RedisSink::start(
+                        chain,
+                        redis.clone(),
+                        settings.stream.clone(),
+                        outbox,
+                        settings.outbox_retry,
+                    )
+                    .await
+                }
+                "websocket" => Arc::new(WebSocketSink {
+                    broadcaster: broadcaster.clone(),
+                }),
+                "stdout" => Arc::new(StdoutSink),
+                "file" => Arc::new(FileSink {
+                    path: match &backfill {
+                        Some(backfill) => backfill.progress_file.with_extension("events.ndjson"),
+                        None => settings.file_path.clone(),
+                    },
+                    max_bytes: settings.file_max_bytes,
+                    max_files: settings.file_max_files,
+                    file: tokio::sync::Mutex::new(None),
+                }),
+                "webhook" => {
+                    let Some(url) = &settings.webhook_url else {
+                        bail!("webhook sink enabled but SINK_WEBHOOK_URL is not set");
+                    };
+                    Arc::new(WebhookSink {
+                        client: reqwest::Client::builder()
+                            .timeout(settings.webhook_timeout)
+                            .build()?,
+                        url: url.expose().to_string(),
+                        authorization: settings
+                            .webhook_authorization
+                            .as_ref()
+                            .map(|authorization| authorization.expose().to_string()),
+                    })
+                }
+                other => unreachable!("unknown sink {}", other),
+            };
+            handles.push(Self::spawn(chain, name, sink, settings.queue(name)));
+        }
+
+        info!(
+            "Sinks: {}",
+            handles
+                .iter()
+                .map(|handle| handle.name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok(Self {
+            chain: chain.to_string(),
+            handles,
+        })
+    }
+
+    /// Start a sink's worker behind a queue of its own.
+    fn spawn(
+        chain: &str,
+        name: &'static str,
+        sink: Arc<dyn Sink>,
+        queue_settings: QueueSettings,
+    ) -> SinkHandle {
+        let QueueSettings {
+            size: queue_size,
+            on_full,
+            max_retries,
+        } = queue_settings;
+
+        let (queue, mut rx) = mpsc::channel::<Arc<Queued>>(queue_size.max(1));
+        let stats = Arc::new(SinkStats::default());
+        let worker_stats = stats.clone();
+        let worker_sink = sink.clone();
+        let worker_chain = chain.to_string();
+        let closing = Arc::new(Notify::new());
+        let worker_closing = closing.clone();
+        let worker = tokio::spawn(
+            async move {
+                loop {
+                    let event = tokio::select! {
+                        event = rx.recv() => event,
+                        // Refuse new events; recv() returns None once the
+                        // queue is empty
+                        _ = worker_closing.notified() => {
+                            rx.close();
+                            continue;
+                        }
+                    };
+                    let Some(queued) = event else {
+                        break;
+                    };
+                    let delivered = deliver(
+                        &worker_chain,
+                        name,
+                        worker_sink.as_ref(),
+                        &queued.event,
+                        max_retries,
+                        &worker_stats,
+                    )
+                    .await;
+                    queued.finish(delivered);
+                }
+            }
+            .in_current_span(),
+        );
+
+        SinkHandle {
+            name,
+            sink,
+            queue,
+            on_full,
+            stats,
+            closing,
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    /// Queue an event on every sink that takes it. The receipt resolves to
+    /// true once every sink has delivered it, and to false (or an error, if
+    /// the process is shutting down) when any sink gave up on it or dropped
+    /// it.
+    pub async fn publish(&self, event: StreamEvent) -> oneshot::Receiver<bool> {
+        let (done, receipt) = oneshot::channel();
+        let handles: Vec<&SinkHandle> = self
+            .handles
+            .iter()
+            .filter(|handle| handle.sink.accepts(&event))
+            .collect();
+        let queued = Arc::new(Queued {
+            event,
+            outcome: Mutex::new(Outcome {
+                remaining: handles.len(),
+                lost: false,
+                done: Some(done),
+            }),
+        });
+        if handles.is_empty() {
+            queued.finish(true);
+        }
+        for handle in handles {
+            match handle.on_full {
+                OnFull::Block => {
+                    if handle.queue.send(queued.clone()).await.is_err() {
+                        error!("{} sink worker has stopped", handle.name);
+                        queued.finish(false);
+                    }
+                }
+                OnFull::Drop => {
+                    if handle.queue.try_send(queued.clone()).is_err() {
+                        queued.finish(false);
+                        let dropped = handle.stats.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                        metrics()
+                            .sink_lost
+                            .with_label_values(&[&self.chain, handle.name, "dropped"])
+                            .inc();
+                        if dropped.is_power_of_two() {
+                            warn!(
+                                "{} sink queue is full; {} events dropped so far",
+                                handle.name, dropped
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        receipt
+    }
+
+    /// Deliver everything still queued, then flush each sink. Nothing can be
+    /// published afterwards.
+    pub async fn close(&self) {
+        for handle in &self.handles {
+            handle.closing.notify_one();
+            let worker = handle
+                .worker
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .take();
+            let Some(worker) = worker else {
+                continue;
+            };
+            if let Err(e) = worker.await {
+                error!("{} sink worker failed: {}", handle.name, e);
+            }
+            handle.sink.flush().await;
+        }
+    }
+
+    /// Whether the named sink's backend is reachable; `None` if the sink
+    /// isn't configured or holds no connection.
+    pub fn connected(&self, name: &str) -> Option<bool> {
+        self.handles
+            .iter()
+            .find(|handle| handle.name == name)
+            .and_then(|handle| handle.sink.connected())
+    }
+
+    /// Sinks that gave up on or dropped any events, with how many of each.
+    pub fn losses(&self) -> Vec<(&'static str, u64, u64)> {
+        self.handles
+            .iter()
+            .map(|handle| {
+                (
+                    handle.name,
+                    handle.stats.failed.load(Ordering::Relaxed),
+                    handle.stats.dropped.load(Ordering::Relaxed),
+                )
+            })
+            .filter(|&(_, failed, dropped)| failed + dropped > 0)
+            .collect()
+    }
+
+    /// Per-sink queue depth and delivery counts, for the health endpoint.
+    pub fn status(&self) -> serde_json::Value {
+        self.handles
+            .iter()
+            .map(|handle| {
+                let stats = &handle.stats;
+                let last_error = stats
+                    .last_error
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone();
+                let mut status = serde_json::json!({
+                    "queued": handle.queue.max_capacity() - handle.queue.capacity(),
+                    "delivered": stats.delivered.load(Ordering::Relaxed),
+                    "failed": stats.failed.load(Ordering::Relaxed),
+                    "dropped": stats.dropped.load(Ordering::Relaxed),
+                    "last_error": last_error,
+                });
+                if let (Some(status), Some(serde_json::Value::Object(extra))) =
+                    (status.as_object_mut(), handle.sink.status())
+                {
+                    status.extend(extra);
+                }
+                (handle.name.to_string(), status)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+}
+
+/// Send one event, retrying with exponential backoff before giving up.
+/// Returns whether it was delivered.
+async fn deliver(
+    chain: &str,
+    name: &str,
+    sink: &dyn Sink,
+    event: &StreamEvent,
+    max_retries: u32,
+    stats: &SinkStats,
+) -> bool {
+    let mut delay = RETRY_BASE_DELAY;
+    let mut attempt = 0;
+    loop {
+        match sink.send(event).await {
+            Ok(()) => {
+                stats.delivered.fetch_add(1, Ordering::Relaxed);
+                return true;
+            }
+            Err(e) if attempt < max_retries => {
+                metrics()
+                    .sink_errors
+                    .with_label_values(&[chain, name])
+                    .inc();
+                warn!("{} sink failed ({:#}); retrying in {:?}", name, e, delay);
+                *stats.last_error.lock().unwrap_or_else(|e| e.into_inner()) =
+                    Some(format!("{:#}", e));
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(RETRY_MAX_DELAY);
+                attempt += 1;
+            }
+            Err(e) => {
+                error!(
+                    "{} sink gave up on {} event after {} attempts: {:#}",
+                    name,
+                    event.kind(),
+                    attempt + 1,
+                    e
+                );
+                stats.failed.fetch_add(1, Ordering::Relaxed);
+                metrics()
+                    .sink_errors
+                    .with_label_values(&[chain, name])
+                    .inc();
+                metrics()
+                    .sink_lost
+                    .with_label_values(&[chain, name, "failed"])
+                    .inc();
+                *stats.last_error.lock().unwrap_or_else(|e| e.into_inner()) =
+                    Some(format!("{:#}", e));
+                return false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransactionData;
+    use warp::{http::StatusCode, Filter as _};
+
+    fn webhook(url: String) -> WebhookSink {
+        WebhookSink {
+            client: reqwest::Client::new(),
+            url,
+            authorization: Some("Bearer hook-secret".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn webhook_errors_never_include_the_url() {
+        let route = warp::any().map(|| StatusCode::INTERNAL_SERVER_ERROR);
+        let (addr, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let event = StreamEvent::Transfer(TransactionData::example(100, 0));
+
+        for (url, expected) in [
+            (format!("http://{}/hooks/hook-token", addr), "500"),
+            (
+                "http://127.0.0.1:1/hooks/hook-token".to_string(),
+                "error sending request",
+            ),
+        ] {
+            let stats = SinkStats::default();
+            assert!(!deliver("base", "webhook", &webhook(url), &event, 0, &stats).await);
+            let last_error = stats.last_error.lock().unwrap().clone().unwrap();
+            assert!(last_error.contains(expected), "{}", last_error);
+            assert!(!last_error.contains("hook-token"), "{}", last_error);
+            assert_eq!(stats.failed.load(Ordering::Relaxed), 1);
+        }
+    }
+
+    fn entry(fields: &[(&str, &str)]) -> outbox::Entry {
+        fields
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn sink_names_are_checked() {
+        assert_eq!(
+            parse_names(" Redis, stdout ,,webhook").unwrap(),
+            ["redis", "stdout", "webhook"]
+        );
+        assert!(parse_names("").unwrap().is_empty());
+        assert!(parse_names("redis,kafka").is_err());
+        assert!(parse_names("file,FILE").is_err());
+    }
+
+    #[test]
+    fn supply_updates_go_to_their_own_stream() {
+        let stream = StreamConfig {
+            key: "stablecoin:transactions".to_string(),
+            supply_key: "stablecoin:supply".to_string(),
+            trim: Trim::None,
+            per_token: true,
+        };
+
+        let transfer = entry(&[("type", "transfer"), ("stablecoin", "USDC")]);
+        assert_eq!(
+            stream.keys(&transfer),
+            ["stablecoin:transactions", "stablecoin:transactions:USDC"]
+        );
+        let supply = entry(&[("type", "supply"), ("stablecoin", "USDC")]);
+        assert_eq!(stream.keys(&supply), ["stablecoin:supply"]);
+    }
+}
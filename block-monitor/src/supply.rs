@@ -0,0 +1,268 @@
+use crate::events::EventId;
+use alloy::primitives::{Address, I256};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Mints and burns whose counted state is kept in memory; older ones are
+/// looked up where the totals are saved
+const COUNTED_CAPACITY: usize = 10_000;
+
+/// What a `Transfer` log represents, based on the zero address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    #[default]
+    Transfer,
+    /// Transfer from the zero address: new tokens issued
+    Mint,
+    /// Transfer to the zero address: tokens redeemed
+    Burn,
+}
+
+impl EventKind {
+    pub fn classify(from: Address, to: Address) -> Self {
+        if from == Address::ZERO {
+            Self::Mint
+        } else if to == Address::ZERO {
+            Self::Burn
+        } else {
+            Self::Transfer
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Transfer => "transfer",
+            Self::Mint => "mint",
+            Self::Burn => "burn",
+        }
+    }
+}
+
+/// Published on the supply stream whenever a mint or burn changes a token's
+/// running net supply.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupplyUpdate {
+    pub chain_id: u64,
+    pub stablecoin: String,
+    /// Token contract address
+    pub token: String,
+    pub event_kind: EventKind,
+    /// Signed change from this event; negative for burns and for retracted
+    /// mints
+    pub amount: String,
+    /// Running net supply change for the token
+    pub net_change: String,
+    pub block_number: u64,
+    pub block_hash: String,
+    pub tx_hash: String,
+    pub log_index: u64,
+    /// Whether this undoes the change of a retracted mint or burn
+    pub retracted: bool,
+}
+
+/// Result of [`SupplyTracker::apply`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum SupplyChange {
+    /// The token's new total
+    Applied(I256),
+    /// The event was already counted, or already undone
+    Unchanged,
+    /// The total would overflow; it's left as it was
+    Overflow,
+}
+
+/// Running net supply change per token address, in raw token units.
+/// Symbols aren't unique, so they can't be used as the key.
+#[derive(Default)]
+pub struct SupplyTracker {
+    totals: HashMap<String, I256>,
+    /// Whether the totals persisted by a previous run have been added in
+    loaded: bool,
+    /// Whether each recent mint or burn is counted in `totals`, so one that's
+    /// published again after a failed delivery isn't counted twice
+    counted: HashMap<EventId, bool>,
+    /// Insertion order of `counted`, oldest first
+    order: VecDeque<EventId>,
+}
+
+impl SupplyTracker {
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    /// Add in totals persisted by a previous run. Changes seen before they
+    /// could be read are kept on top.
+    pub fn load(&mut self, stored: HashMap<String, I256>) {
+        for (token, total) in stored {
+            let current = self.totals.entry(token).or_default();
+            *current = current.saturating_add(total);
+        }
+        self.loaded = true;
+    }
+
+    /// Whether `event`'s counted state is known in memory.
+    pub fn knows(&self, event: &EventId) -> bool {
+        self.counted.contains_key(event)
+    }
+
+    /// Record whether `event` is counted, e.g. as saved by a previous run.
+    pub fn remember(&mut self, event: &EventId, counted: bool) {
+        if self.counted.insert(event.clone(), counted).is_some() {
+            return;
+        }
+        self.order.push_back(event.clone());
+        while self.order.len() > COUNTED_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.counted.remove(&oldest);
+            }
+        }
+    }
+
+    /// Add `delta` for `event` to the token's total, or, when `retracted`,
+    /// undo it. Counting an event that's already counted, or undoing one
+    /// that isn't, changes nothing.
+    pub fn apply(
+        &mut self,
+        event: &EventId,
+        token: &str,
+        delta: I256,
+        retracted: bool,
+    ) -> SupplyChange {
+        let counted = self.counted.get(event).copied().unwrap_or(false);
+        if counted != retracted {
+            return SupplyChange::Unchanged;
+        }
+        let total = self.totals.entry(token.to_string()).or_default();
+        let Some(new_total) = total.checked_add(delta) else {
+            return SupplyChange::Overflow;
+        };
+        *total = new_total;
+        self.remember(event, !retracted);
+        SupplyChange::Applied(new_total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913";
+
+    fn event(log_index: u64) -> EventId {
+        EventId::new("0xabc", log_index)
+    }
+
+    fn amount(value: i64) -> I256 {
+        I256::try_from(value).unwrap()
+    }
+
+    #[test]
+    fn transfers_are_classified_by_the_zero_address() {
+        let holder = Address::repeat_byte(0x11);
+        assert_eq!(EventKind::classify(Address::ZERO, holder), EventKind::Mint);
+        assert_eq!(EventKind::classify(holder, Address::ZERO), EventKind::Burn);
+        assert_eq!(EventKind::classify(holder, holder), EventKind::Transfer);
+    }
+
+    #[test]
+    fn each_event_is_counted_once() {
+        let mut supply = SupplyTracker::default();
+        assert_eq!(
+            supply.apply(&event(0), TOKEN, amount(100), false),
+            SupplyChange::Applied(amount(100))
+        );
+        // Published again after a failed delivery
+        assert_eq!(
+            supply.apply(&event(0), TOKEN, amount(100), false),
+            SupplyChange::Unchanged
+        );
+        assert_eq!(
+            supply.apply(&event(1), TOKEN, amount(-30), false),
+            SupplyChange::Applied(amount(70))
+        );
+    }
+
+    #[test]
+    fn retractions_undo_counted_events_once() {
+        let mut supply = SupplyTracker::default();
+        // Never counted, so nothing to undo
+        assert_eq!(
+            supply.apply(&event(0), TOKEN, amount(-100), true),
+            SupplyChange::Unchanged
+        );
+
+        supply.apply(&event(0), TOKEN, amount(100), false);
+        assert_eq!(
+            supply.apply(&event(0), TOKEN, amount(-100), true),
+            SupplyChange::Applied(amount(0))
+        );
+        assert_eq!(
+            supply.apply(&event(0), TOKEN, amount(-100), true),
+            SupplyChange::Unchanged
+        );
+        // Counted again if it comes back
+        assert_eq!(
+            supply.apply(&event(0), TOKEN, amount(100), false),
+            SupplyChange::Applied(amount(100))
+        );
+    }
+
+    #[test]
+    fn events_counted_by_a_previous_run_are_skipped() {
+        let mut supply = SupplyTracker::default();
+        supply.load(HashMap::from([(TOKEN.to_string(), amount(500))]));
+        supply.remember(&event(0), true);
+        assert!(supply.knows(&event(0)));
+        assert!(!supply.knows(&event(1)));
+
+        assert_eq!(
+            supply.apply(&event(0), TOKEN, amount(100), false),
+            SupplyChange::Unchanged
+        );
+        assert_eq!(
+            supply.apply(&event(1), TOKEN, amount(100), false),
+            SupplyChange::Applied(amount(600))
+        );
+    }
+
+    #[test]
+    fn saved_totals_are_added_to_changes_seen_before_loading() {
+        let mut supply = SupplyTracker::default();
+        supply.apply(&event(0), TOKEN, amount(10), false);
+        assert!(!supply.is_loaded());
+
+        supply.load(HashMap::from([(TOKEN.to_string(), amount(500))]));
+        assert!(supply.is_loaded());
+        assert_eq!(
+            supply.apply(&event(1), TOKEN, amount(1), false),
+            SupplyChange::Applied(amount(511))
+        );
+    }
+
+    #[test]
+    fn overflow_leaves_the_total_and_the_event_uncounted() {
+        let mut supply = SupplyTracker::default();
+        supply.apply(&event(0), TOKEN, I256::MAX, false);
+        assert_eq!(
+            supply.apply(&event(1), TOKEN, amount(1), false),
+            SupplyChange::Overflow
+        );
+        assert!(!supply.knows(&event(1)));
+        assert_eq!(
+            supply.apply(&event(2), TOKEN, amount(-1), false),
+            SupplyChange::Applied(I256::MAX - amount(1))
+        );
+    }
+
+    #[test]
+    fn only_recent_events_are_remembered() {
+        let mut supply = SupplyTracker::default();
+        for log_index in 0..=COUNTED_CAPACITY as u64 {
+            supply.remember(&event(log_index), true);
+        }
+        assert!(!supply.knows(&event(0)));
+        assert!(supply.knows(&event(1)));
+        assert!(supply.knows(&event(COUNTED_CAPACITY as u64)));
+    }
+}
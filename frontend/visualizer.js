@@ -4240,9 +4240,15 @@ function onWindowResize() {
     renderer.setSize(container.clientWidth, container.clientHeight);
 }
 
+// USD value of a transfer; falls back to the formatted amount for servers
+// that don't send usd_value
+function usdValue(data) {
+    return typeof data.usd_value === 'number' ? data.usd_value : parseFloat(data.amount);
+}
+
 // Add a new transaction - animals + fruits on trees
 function addTransaction(data) {
-    const amount = parseFloat(data.amount);
+    const amount = usdValue(data);
     
     // Add to transaction feed
     addToTransactionFeed(data);
@@ -4264,13 +4270,18 @@ function addTransaction(data) {
     // Always create animal for transaction
     const animal = new TransactionAnimal(
         data.stablecoin,
-        data.amount,
+        amount,
         data.from,
         data.to
     );
     
     // Store the stablecoin type on the animal for field display
     animal.stablecoin = stablecoin;
+    animal.txHash = data.tx_hash;
+    animal.eventId = eventId(data);
+    animal.timestamp = data.timestamp;
+    animal.chainId = data.chain_id;
+    animal.eventKind = data.event_kind || 'transfer';
     
     animals.push(animal);
     scene.add(animal.mesh);
@@ -4314,6 +4325,23 @@ function removeOldestPlants(count) {
     console.log(`Removed ${plantsToRemove.length} oldest plants. Current count: ${particles.length}`);
 }
 
+// Remove animals whose transaction was reorged out of the chain
+function removeRetractedTransaction(data) {
+    const id = eventId(data);
+    const retracted = animals.filter(animal => animal.eventId === id);
+    if (retracted.length === 0) return;
+    
+    animals = animals.filter(animal => animal.eventId !== id);
+    retracted.forEach(animal => {
+        scene.remove(animal.mesh);
+        animal.dispose();
+    });
+    stats.currentAnimals = animals.length;
+    updateStats();
+    
+    console.log(`Removed ${retracted.length} animals for retracted transfer ${id}`);
+}
+
 // Remove the oldest animals from the garden
 function removeOldestAnimals(count) {
     // Sort by creation time and remove the oldest
@@ -4440,7 +4468,7 @@ function addToTransactionFeed(data) {
     txItem.className = 'transaction-item';
     
     const coin = data.stablecoin.toLowerCase();
-    const amount = parseFloat(data.amount);
+    const amount = usdValue(data);
     const time = new Date().toLocaleTimeString('en-US', { 
         hour12: false, 
         hour: '2-digit', 
@@ -4957,10 +4985,15 @@ function setupWebSocketListeners() {
         
         // Update statistics only - animals are spawned via spawn:animal event
         stats.transactions++;
-        stats.volume += parseFloat(data.amount) || 0;
+        stats.volume += usdValue(data) || 0;
         updateStats();
     });
     
+    // Remove animals for transactions that were reorged out
+    wsManager.addEventListener('transaction:retracted', (event) => {
+        removeRetractedTransaction(event.detail);
+    });
+    
     // Listen for spawn:animal events from the spawn queue
     window.addEventListener('spawn:animal', (event) => {
         const data = event.detail;
@@ -9,10 +9,22 @@
  * - 'connection:close' - WebSocket disconnected  
  * - 'connection:error' - Connection error occurred
  * - 'transaction' - New transaction received
+ * - 'transaction:retracted' - A transaction was reorged out of the chain
+ * - 'transaction:confirmed' - A transaction already shown as pending was confirmed
+ * - 'token:approval' - An allowance was set, directly or through a permit
+ * - 'token:admin' - An issuer blacklisted an account, seized funds or paused a token
  * - 'spawn:animal' - Animal ready to spawn from queue
  * - 'status:change' - Connection status changed
  */
 
+/**
+ * Canonical event ID: the transaction hash plus the log's index in the block.
+ * Older servers don't send log_index, so fall back to the hash alone.
+ */
+function eventId(data) {
+    return data.log_index === undefined ? data.tx_hash : `${data.tx_hash}:${data.log_index}`;
+}
+
 /**
  * Spawn Queue Manager
  * Controls the rate at which animals spawn and ensures balanced size distribution
@@ -146,6 +158,13 @@ class SpawnQueue {
         }
     }
     
+    /**
+     * Drop queued transactions with the given event ID
+     */
+    remove(id) {
+        this.queue = this.queue.filter(item => eventId(item.data) !== id);
+    }
+    
     /**
      * Clear the spawn queue
      */
@@ -220,6 +239,11 @@ class WebSocketManager extends EventTarget {
             wsEndpoint: '/ws'
         };
         
+        // IDs of transfers already shown as pending, so the follow-up
+        // "confirmed" event doesn't spawn a second animal
+        this.pendingHashes = new Set();
+        this.maxPendingHashes = 5000;
+        
         // Statistics
         this.stats = {
             messagesReceived: 0,
@@ -295,6 +319,31 @@ class WebSocketManager extends EventTarget {
                 this.stats.messagesReceived++;
                 this.stats.lastMessageTime = Date.now();
                 
+                // Reorged-out transfers: drop them if they haven't spawned yet,
+                // and let the visualizer remove them if they have
+                if (data.type === 'retracted') {
+                    this.spawnQueue.remove(eventId(data));
+                    this.dispatchEvent(new CustomEvent('transaction:retracted', { detail: data }));
+                    return;
+                }
+                
+                // Approvals and issuer actions don't spawn animals
+                if (data.type === 'approval' || data.type === 'admin') {
+                    this.dispatchEvent(new CustomEvent(`token:${data.type}`, { detail: data }));
+                    return;
+                }
+                
+                if (data.status === 'confirmed' && this.pendingHashes.has(eventId(data))) {
+                    this.dispatchEvent(new CustomEvent('transaction:confirmed', { detail: data }));
+                    return;
+                }
+                if (data.status === 'pending') {
+                    if (this.pendingHashes.size >= this.maxPendingHashes) {
+                        this.pendingHashes.clear();
+                    }
+                    this.pendingHashes.add(eventId(data));
+                }
+                
                 // Add transaction to spawn queue instead of immediately emitting
                 this.spawnQueue.enqueue(data);
                 
@@ -0,0 +1,58 @@
+use std::collections::{HashSet, VecDeque};
+
+/// Bounded set of recently broadcast event IDs; the oldest are forgotten
+/// first.
+pub struct RecentEvents {
+    capacity: usize,
+    ids: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl RecentEvents {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            ids: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns false if `id` was already seen.
+    pub fn insert(&mut self, id: String) -> bool {
+        if self.ids.contains(&id) {
+            return false;
+        }
+        self.ids.insert(id.clone());
+        self.order.push_back(id);
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_recent_events_are_remembered() {
+        let mut recent = RecentEvents::new(2);
+        assert!(recent.insert("a".to_string()));
+        assert!(recent.insert("b".to_string()));
+        assert!(!recent.insert("a".to_string()));
+        assert!(recent.insert("c".to_string()));
+        // "a" was the oldest
+        assert!(recent.insert("a".to_string()));
+        assert!(!recent.insert("c".to_string()));
+    }
+
+    #[test]
+    fn capacity_is_at_least_one() {
+        let mut recent = RecentEvents::new(0);
+        assert!(recent.insert("a".to_string()));
+        assert!(!recent.insert("a".to_string()));
+    }
+}
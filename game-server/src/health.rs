@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use warp::http::StatusCode;
+
+/// Consumer loop state behind `/health/live` and `/health/ready`.
+pub struct ConsumerHealth {
+    /// The loop is considered stuck after going this long without a pass
+    heartbeat_timeout: Duration,
+    /// When the consumer loop last came round, whether or not it read anything
+    heartbeat: Mutex<Instant>,
+    /// Whether the last stream read reached Redis
+    redis_connected: AtomicBool,
+    /// Set once shutdown starts, so traffic moves to other instances
+    stopping: AtomicBool,
+}
+
+impl ConsumerHealth {
+    pub fn new(heartbeat_timeout: Duration) -> Self {
+        Self {
+            heartbeat_timeout,
+            heartbeat: Mutex::new(Instant::now()),
+            redis_connected: AtomicBool::new(true),
+            stopping: AtomicBool::new(false),
+        }
+    }
+
+    /// Fail readiness from now on; the server is shutting down.
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::Relaxed);
+    }
+
+    /// Record a pass of the consumer loop and whether its read reached Redis.
+    pub fn beat(&self, redis_connected: bool) {
+        *self.heartbeat.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+        self.redis_connected
+            .store(redis_connected, Ordering::Relaxed);
+    }
+
+    /// Live while the consumer loop keeps coming round.
+    pub fn live(&self) -> (StatusCode, serde_json::Value) {
+        let heartbeat = self.heartbeat_check();
+        let ok = heartbeat["ok"] == true;
+        report(ok, serde_json::json!({ "heartbeat": heartbeat }))
+    }
+
+    /// Ready while the consumer loop is live and reading from Redis, until
+    /// shutdown starts.
+    pub fn ready(&self) -> (StatusCode, serde_json::Value) {
+        let heartbeat = self.heartbeat_check();
+        let redis_connected = self.redis_connected.load(Ordering::Relaxed);
+        let stopping = self.stopping.load(Ordering::Relaxed);
+        let ok = heartbeat["ok"] == true && redis_connected && !stopping;
+        report(
+            ok,
+            serde_json::json!({
+                "heartbeat": heartbeat,
+                "redis": { "ok": redis_connected },
+                "shutdown": { "ok": !stopping },
+            }),
+        )
+    }
+
+    fn heartbeat_check(&self) -> serde_json::Value {
+        let since = self
+            .heartbeat
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .elapsed();
+        serde_json::json!({
+            "ok": since <= self.heartbeat_timeout,
+            "seconds_ago": since.as_secs(),
+            "timeout_secs": self.heartbeat_timeout.as_secs(),
+        })
+    }
+}
+
+fn report(ok: bool, checks: serde_json::Value) -> (StatusCode, serde_json::Value) {
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = serde_json::json!({
+        "status": if ok { "ok" } else { "failing" },
+        "checks": checks,
+    });
+    (status, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready_until_redis_is_lost() {
+        let health = ConsumerHealth::new(Duration::from_secs(60));
+        assert_eq!(health.ready().0, StatusCode::OK);
+
+        health.beat(false);
+        let (status, body) = health.ready();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["redis"]["ok"], false);
+        assert_eq!(health.live().0, StatusCode::OK);
+
+        health.beat(true);
+        assert_eq!(health.ready().0, StatusCode::OK);
+    }
+
+    #[test]
+    fn not_ready_once_stopping() {
+        let health = ConsumerHealth::new(Duration::from_secs(60));
+        health.stop();
+
+        let (status, body) = health.ready();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["shutdown"]["ok"], false);
+        // Still live: the process is finishing up, not stuck
+        assert_eq!(health.live().0, StatusCode::OK);
+    }
+
+    #[test]
+    fn not_live_after_a_stalled_heartbeat() {
+        let health = ConsumerHealth::new(Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(5));
+
+        assert_eq!(health.live().0, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(health.ready().0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -1,12 +1,22 @@
+mod config;
+mod dedup;
+mod health;
+
+use clap::Parser;
+use config::Config;
+use dedup::RecentEvents;
 use eyre::Result;
 use futures_util::{SinkExt, StreamExt};
+use health::ConsumerHealth;
 use redis::aio::MultiplexedConnection;
 use redis::streams::{StreamReadOptions, StreamReadReply};
 use redis::{AsyncCommands, Client};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, Notify, RwLock};
 use tokio::time::{sleep, Duration};
 use tracing::{error, info, warn};
 use warp::ws::{Message, WebSocket};
@@ -15,52 +25,219 @@ use warp::Filter;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct TransactionData {
     stablecoin: String,
+    /// Token contract address; missing on entries from older block-monitors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
     amount: String,
+    /// Exact amount in token units as a decimal string; missing, like
+    /// `decimals` and `usd_value`, on entries from older block-monitors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    raw_amount: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decimals: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    usd_value: Option<f64>,
     from: String,
     to: String,
     block_number: u64,
     tx_hash: String,
+    /// Block timestamp in Unix seconds; missing on entries from older
+    /// block-monitors, as are the hash and indices below
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    block_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tx_index: Option<u64>,
+    /// Together with `tx_hash`, identifies the event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log_index: Option<u64>,
+    /// EIP-155 chain ID; missing on entries from single-chain block-monitors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chain_id: Option<u64>,
+    /// "transfer", "mint" or "burn"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    event_kind: Option<String>,
+    /// "pending" at the chain head or "confirmed" once deep enough
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+}
+
+/// An allowance change, mirrored from block-monitor.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ApprovalData {
+    stablecoin: String,
+    owner: String,
+    spender: String,
+    amount: String,
+    /// Set through a signed EIP-2612 permit rather than by the owner
+    #[serde(default)]
+    via_permit: bool,
+    block_number: u64,
+    tx_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    block_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tx_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chain_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+}
+
+/// An issuer action such as blacklisting an account or pausing the token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AdminEventData {
+    stablecoin: String,
+    /// "blacklisted", "un_blacklisted", "destroyed_black_funds", "pause", ...
+    action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    account: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    amount: Option<String>,
+    block_number: u64,
+    tx_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    block_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tx_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    log_index: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chain_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+}
+
+/// Events published by block-monitor, tagged by the stream's `type` field.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    Transfer(TransactionData),
+    /// A transfer that was reorged out; clients should remove its animal
+    Retracted(TransactionData),
+    Approval(ApprovalData),
+    Admin(AdminEventData),
+}
+
+impl StreamEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Transfer(_) => "transfer",
+            Self::Retracted(_) => "retracted",
+            Self::Approval(_) => "approval",
+            Self::Admin(_) => "admin",
+        }
+    }
+
+    /// Same ID block-monitor deduplicates publications by. Entries from
+    /// older block-monitors have no log index and aren't deduplicated.
+    fn dedup_key(&self) -> Option<String> {
+        let (chain_id, status, block_hash, tx_hash, log_index) = match self {
+            Self::Transfer(data) | Self::Retracted(data) => (
+                data.chain_id,
+                &data.status,
+                &data.block_hash,
+                &data.tx_hash,
+                data.log_index,
+            ),
+            Self::Approval(data) => (
+                data.chain_id,
+                &data.status,
+                &data.block_hash,
+                &data.tx_hash,
+                data.log_index,
+            ),
+            Self::Admin(data) => (
+                data.chain_id,
+                &data.status,
+                &data.block_hash,
+                &data.tx_hash,
+                data.log_index,
+            ),
+        };
+        Some(format!(
+            "{}:{}:{}:{}:{}:{}",
+            chain_id?,
+            self.kind(),
+            status.as_deref()?,
+            block_hash.as_deref()?,
+            tx_hash,
+            log_index?
+        ))
+    }
+}
+
+/// Command-line flags. Everything else is configured through the
+/// environment or the config file; flags take precedence over both.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Broadcasts stablecoin events from a Redis stream to WebSocket clients"
+)]
+struct Cli {
+    /// TOML file with settings named like the environment variables
+    #[arg(long, env = "CONFIG_FILE")]
+    config: Option<PathBuf>,
+
+    /// WebSocket and health check port; overrides PORT
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// Stream to consume; overrides REDIS_STREAM_KEY
+    #[arg(long)]
+    stream_key: Option<String>,
+
+    /// Consumer group to read as; overrides CONSUMER_GROUP
+    #[arg(long)]
+    consumer_group: Option<String>,
+
+    /// Name within the consumer group; overrides CONSUMER_NAME
+    #[arg(long)]
+    consumer_name: Option<String>,
+}
+
+impl Cli {
+    fn overrides(&self) -> HashMap<String, String> {
+        [
+            ("PORT", self.port.map(|port| port.to_string())),
+            ("REDIS_STREAM_KEY", self.stream_key.clone()),
+            ("CONSUMER_GROUP", self.consumer_group.clone()),
+            ("CONSUMER_NAME", self.consumer_name.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value?)))
+        .collect()
+    }
 }
 
 type Clients = Arc<RwLock<HashMap<String, tokio::sync::mpsc::UnboundedSender<Message>>>>;
 
+/// Close code for "service restart": the client should reconnect
+const RESTART_CLOSE_CODE: u16 = 1012;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv::dotenv().ok();
+    let cli = Cli::parse();
     tracing_subscriber::fmt::init();
 
     info!("Starting Game Server");
 
-    let redis_url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_string());
-    let port = std::env::var("PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse::<u16>()?;
-    let health_port = std::env::var("HEALTH_PORT")
-        .unwrap_or_else(|_| "8081".to_string())
-        .parse::<u16>()?;
-
-    info!("Configuration:");
-    info!(
-        "  Redis URL: {}",
-        if redis_url.contains("@") {
-            let parts: Vec<&str> = redis_url.split('@').collect();
-            if parts.len() > 1 {
-                format!("redis://***@{}", parts[1])
-            } else {
-                "redis://***".to_string()
-            }
-        } else {
-            redis_url.clone()
-        }
-    );
-    info!("  Stream Key: stablecoin:transactions");
-    info!("  WebSocket Port: {}", port);
-    info!("  Health Port: {}", health_port);
+    let config = Config::load(cli.config.clone(), cli.overrides())?;
+    config.log_summary();
 
     let clients: Clients = Arc::new(RwLock::new(HashMap::new()));
+    let health = Arc::new(ConsumerHealth::new(config.heartbeat_timeout));
 
     info!("Connecting to Redis...");
-    let redis_client = match Client::open(redis_url.clone()) {
+    let redis_client = match Client::open(config.redis_url.expose()) {
         Ok(client) => {
             info!("Redis client created successfully");
             client
@@ -83,51 +260,114 @@ async fn main() -> Result<()> {
         }
     };
 
+    let (stop, stopping) = watch::channel(false);
+
     let redis_conn_clone = redis_conn.clone();
     let clients_clone = clients.clone();
-    tokio::spawn(async move {
-        if let Err(e) = consume_redis_stream(redis_conn_clone, clients_clone).await {
+    let health_clone = health.clone();
+    let consumer_stopping = stopping.clone();
+    let consumer_config = config.clone();
+    let consumer = tokio::spawn(async move {
+        if let Err(e) = consume_redis_stream(
+            redis_conn_clone,
+            clients_clone,
+            health_clone,
+            consumer_stopping,
+            &consumer_config,
+        )
+        .await
+        {
             error!("Redis stream consumer error: {:?}", e);
         }
     });
 
-    tokio::spawn(start_health_server(health_port));
-
-    let ws_route = warp::path("ws")
-        .and(warp::ws())
-        .and(with_clients(clients.clone()))
-        .map(|ws: warp::ws::Ws, clients| {
-            ws.on_upgrade(move |socket| client_connected(socket, clients))
-        });
+    let connections = Connections::default();
+    let ws_route = websocket_route(clients.clone(), connections.clone());
 
     let cors = warp::cors()
         .allow_any_origin()
         .allow_methods(vec!["GET", "POST"])
         .allow_headers(vec!["content-type"]);
 
-    let routes = ws_route.with(cors);
+    let routes = ws_route.or(health_routes(health.clone())).with(cors);
 
-    info!("WebSocket server starting on port {}", port);
-    warp::serve(routes).run(([0, 0, 0, 0], port)).await;
+    // New connections are refused once stopping. The server returns without
+    // waiting for WebSocket clients, which are counted in `connections`.
+    let mut server_stopping = stopping;
+    let (_, server) =
+        warp::serve(routes).bind_with_graceful_shutdown(([0, 0, 0, 0], config.port), async move {
+            let _ = server_stopping.wait_for(|stopping| *stopping).await;
+        });
+    info!(
+        "WebSocket server starting on port {} (health on /health)",
+        config.port
+    );
+    let server = tokio::spawn(server);
+
+    shutdown_signal().await;
+    info!("Received shutdown signal, stopping gracefully...");
+    health.stop();
+    let _ = stop.send(true);
+
+    // Finish and acknowledge the batch in hand, then ask clients to
+    // reconnect, e.g. to the instance replacing this one
+    let drained = tokio::time::timeout(config.shutdown_timeout, async {
+        let _ = consumer.await;
+        close_clients(&clients).await;
+        let _ = server.await;
+        connections.closed().await;
+    })
+    .await;
+    if drained.is_err() {
+        warn!(
+            "Shutdown didn't finish within {:?}; exiting anyway",
+            config.shutdown_timeout
+        );
+    }
 
+    info!("Server shutdown complete");
     Ok(())
 }
 
-async fn consume_redis_stream(mut conn: MultiplexedConnection, clients: Clients) -> Result<()> {
-    let stream_key =
-        std::env::var("REDIS_STREAM_KEY").unwrap_or_else(|_| "stablecoin:transactions".to_string());
-    let consumer_group =
-        std::env::var("CONSUMER_GROUP").unwrap_or_else(|_| "websocket-publisher".to_string());
-    let consumer_name = std::env::var("CONSUMER_NAME")
-        .unwrap_or_else(|_| format!("consumer-{}", uuid::Uuid::new_v4()));
+/// Resolves on SIGINT, or on SIGTERM as sent by the platform before it
+/// stops an instance.
+///
+/// block-monitor's `main.rs` has a copy; keep the two in sync.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Can't listen for SIGTERM: {}", e),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+async fn consume_redis_stream(
+    mut conn: MultiplexedConnection,
+    clients: Clients,
+    health: Arc<ConsumerHealth>,
+    stopping: watch::Receiver<bool>,
+    config: &Config,
+) -> Result<()> {
+    let stream_key = &config.stream_key;
+    let consumer_group = &config.consumer_group;
+    let consumer_name = &config.consumer_name;
+    let mut recent = RecentEvents::new(config.dedup_capacity);
 
-    info!("Starting Redis stream consumer:");
-    info!("  Stream: {}", stream_key);
-    info!("  Consumer Group: {}", consumer_group);
-    info!("  Consumer Name: {}", consumer_name);
+    info!("Starting Redis stream consumer on {}", stream_key);
 
     let _: Result<(), redis::RedisError> = conn
-        .xgroup_create_mkstream(&stream_key, &consumer_group, "$")
+        .xgroup_create_mkstream(stream_key, consumer_group, "$")
         .await
         .or_else(|e| {
             if e.to_string().contains("BUSYGROUP") {
@@ -145,16 +385,24 @@ async fn consume_redis_stream(mut conn: MultiplexedConnection, clients: Clients)
     let mut total_messages = 0u64;
     let mut last_log_time = std::time::Instant::now();
 
-    loop {
+    // Stopping is checked between batches, so the one in hand is always
+    // broadcast and acknowledged
+    while !*stopping.borrow() {
         let options = StreamReadOptions::default()
-            .group(&consumer_group, &consumer_name)
+            .group(consumer_group, consumer_name)
             .count(10)
             .block(1000);
 
         let result: Result<StreamReadReply, redis::RedisError> = conn
-            .xread_options(&[&stream_key], &[&last_id], &options)
+            .xread_options(&[stream_key], &[&last_id], &options)
             .await;
 
+        // A blocking read that times out still reached Redis
+        health.beat(match &result {
+            Ok(_) => true,
+            Err(e) => e.to_string().contains("timeout"),
+        });
+
         match result {
             Ok(reply) => {
                 let message_count = reply.keys.iter().map(|k| k.ids.len()).sum::<usize>();
@@ -178,23 +426,56 @@ async fn consume_redis_stream(mut conn: MultiplexedConnection, clients: Clients)
                             }
                         }
 
-                        if let Some(data) = parse_stream_data(&stream_id.map) {
+                        if let Some(event) = parse_stream_data(&stream_id.map) {
+                            // Already broadcast, e.g. republished after a
+                            // block-monitor restart
+                            if let Some(key) = event.dedup_key() {
+                                if !recent.insert(key) {
+                                    info!("Dropping duplicate {} {}", event.kind(), stream_id.id);
+                                    let _: Result<(), redis::RedisError> = conn
+                                        .xack(stream_key, consumer_group, &[&stream_id.id])
+                                        .await;
+                                    continue;
+                                }
+                            }
+
                             total_messages += 1;
-                            info!(
-                                "✅ Transaction #{}: {} ${} from {} to {}",
-                                total_messages,
-                                data.stablecoin,
-                                data.amount,
-                                &data.from[..10],
-                                &data.to[..10]
-                            );
+                            match &event {
+                                StreamEvent::Transfer(data) => info!(
+                                    "✅ Transaction #{}: {} ${} from {} to {}",
+                                    total_messages,
+                                    data.stablecoin,
+                                    data.amount,
+                                    &data.from[..10],
+                                    &data.to[..10]
+                                ),
+                                StreamEvent::Retracted(data) => info!(
+                                    "↩️ Retraction #{}: {} ${} in tx {}",
+                                    total_messages, data.stablecoin, data.amount, data.tx_hash
+                                ),
+                                StreamEvent::Approval(data) => info!(
+                                    "🔑 Approval #{}: {} {} for {}{}",
+                                    total_messages,
+                                    data.stablecoin,
+                                    data.amount,
+                                    data.spender,
+                                    if data.via_permit { " (permit)" } else { "" }
+                                ),
+                                StreamEvent::Admin(data) => info!(
+                                    "🛑 Admin #{}: {} {} {}",
+                                    total_messages,
+                                    data.stablecoin,
+                                    data.action,
+                                    data.account.as_deref().unwrap_or("")
+                                ),
+                            }
 
                             let client_count = clients.read().await.len();
                             info!("Broadcasting to {} connected clients", client_count);
-                            broadcast_to_clients(&clients, &data).await;
+                            broadcast_to_clients(&clients, &event).await;
 
                             let _: Result<(), redis::RedisError> = conn
-                                .xack(&stream_key, &consumer_group, &[&stream_id.id])
+                                .xack(stream_key, consumer_group, &[&stream_id.id])
                                 .await;
                         } else {
                             warn!("Failed to parse message data from stream");
@@ -230,9 +511,12 @@ async fn consume_redis_stream(mut conn: MultiplexedConnection, clients: Clients)
             }
         }
     }
+
+    info!("Stopped consuming after {} messages", total_messages);
+    Ok(())
 }
 
-fn parse_stream_data(data: &HashMap<String, redis::Value>) -> Option<TransactionData> {
+fn parse_stream_data(data: &HashMap<String, redis::Value>) -> Option<StreamEvent> {
     let get_string = |key: &str| -> Option<String> {
         data.get(key).and_then(|v| match v {
             redis::Value::BulkString(bytes) => String::from_utf8(bytes.clone()).ok(),
@@ -242,17 +526,53 @@ fn parse_stream_data(data: &HashMap<String, redis::Value>) -> Option<Transaction
 
     let get_u64 = |key: &str| -> Option<u64> { get_string(key).and_then(|s| s.parse().ok()) };
 
-    Some(TransactionData {
+    // Approval and admin entries carry their fields in the JSON `data`
+    if let Some(kind @ ("approval" | "admin")) = get_string("type").as_deref() {
+        return match get_string("data").map(|json| serde_json::from_str(&json)) {
+            Some(Ok(event)) => Some(event),
+            Some(Err(e)) => {
+                warn!("Ignoring malformed {} stream entry: {}", kind, e);
+                None
+            }
+            None => {
+                warn!("Ignoring {} stream entry without data", kind);
+                None
+            }
+        };
+    }
+
+    let transaction = TransactionData {
         stablecoin: get_string("stablecoin")?,
+        token: get_string("token"),
         amount: get_string("amount")?,
+        raw_amount: get_string("raw_amount"),
+        decimals: get_string("decimals").and_then(|s| s.parse().ok()),
+        usd_value: get_string("usd_value").and_then(|s| s.parse().ok()),
         from: get_string("from")?,
         to: get_string("to")?,
         block_number: get_u64("block")?, // Block-monitor sends "block", not "block_number"
         tx_hash: get_string("tx_hash")?,
-    })
+        timestamp: get_u64("timestamp"),
+        block_hash: get_string("block_hash"),
+        tx_index: get_u64("tx_index"),
+        log_index: get_u64("log_index"),
+        chain_id: get_u64("chain_id"),
+        event_kind: get_string("event_kind"),
+        status: get_string("status"),
+    };
+
+    // Entries written before block-monitor tagged events are all transfers
+    match get_string("type").as_deref() {
+        None | Some("transfer") => Some(StreamEvent::Transfer(transaction)),
+        Some("retracted") => Some(StreamEvent::Retracted(transaction)),
+        Some(other) => {
+            warn!("Ignoring stream entry with unknown type {}", other);
+            None
+        }
+    }
 }
 
-async fn broadcast_to_clients(clients: &Clients, data: &TransactionData) {
+async fn broadcast_to_clients(clients: &Clients, data: &StreamEvent) {
     let message = match serde_json::to_string(data) {
         Ok(json) => Message::text(json),
         Err(e) => {
@@ -287,7 +607,21 @@ fn with_clients(
     warp::any().map(move || clients.clone())
 }
 
-async fn client_connected(ws: WebSocket, clients: Clients) {
+/// `/ws`: registers each client for broadcasts, counted in `connections`.
+fn websocket_route(
+    clients: Clients,
+    connections: Connections,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("ws")
+        .and(warp::ws())
+        .and(with_clients(clients))
+        .map(move |ws: warp::ws::Ws, clients| {
+            let connection = connections.open();
+            ws.on_upgrade(move |socket| client_connected(socket, clients, connection))
+        })
+}
+
+async fn client_connected(ws: WebSocket, clients: Clients, connection: Connection) {
     let (mut client_ws_tx, mut client_ws_rx) = ws.split();
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
 
@@ -304,12 +638,15 @@ async fn client_connected(ws: WebSocket, clients: Clients) {
         client_id, client_count
     );
 
+    // Counted until the close frame is out, or the client is gone
     tokio::spawn(async move {
         while let Some(message) = rx.recv().await {
-            if client_ws_tx.send(message).await.is_err() {
+            let closing = message.is_close();
+            if client_ws_tx.send(message).await.is_err() || closing {
                 break;
             }
         }
+        drop(connection);
     });
 
     while client_ws_rx.next().await.is_some() {}
@@ -325,10 +662,226 @@ async fn client_connected(ws: WebSocket, clients: Clients) {
     );
 }
 
-async fn start_health_server(port: u16) {
+/// Open WebSocket connections, counted so shutdown can wait for their close
+/// frames to go out.
+///
+/// block-monitor's `server.rs` has a copy; keep the two in sync.
+#[derive(Clone, Default)]
+struct Connections {
+    shared: Arc<ConnectionCount>,
+}
+
+#[derive(Default)]
+struct ConnectionCount {
+    open: AtomicUsize,
+    closed: Notify,
+}
+
+/// Counts as an open connection until dropped.
+struct Connection(Connections);
+
+impl Connections {
+    fn open(&self) -> Connection {
+        self.shared.open.fetch_add(1, Ordering::SeqCst);
+        Connection(self.clone())
+    }
+
+    /// Resolves once no connections are open.
+    async fn closed(&self) {
+        loop {
+            let closed = self.shared.closed.notified();
+            if self.shared.open.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            closed.await;
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if self.0.shared.open.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.shared.closed.notify_waiters();
+        }
+    }
+}
+
+/// Send every client a close frame asking it to reconnect.
+async fn close_clients(clients: &Clients) {
+    let clients = clients.read().await;
+    info!("Closing {} client connections", clients.len());
+    for tx in clients.values() {
+        let _ = tx.send(Message::close_with(
+            RESTART_CLOSE_CODE,
+            "server restarting, reconnect",
+        ));
+    }
+}
+
+/// `/health` (always OK), and `/health/live` and `/health/ready`, which
+/// answer 503 when the consumer is stuck or has lost Redis.
+fn health_routes(
+    consumer: Arc<ConsumerHealth>,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let health =
-        warp::path("health").map(|| warp::reply::with_status("OK", warp::http::StatusCode::OK));
+        warp::path!("health").map(|| warp::reply::with_status("OK", warp::http::StatusCode::OK));
+    let live = warp::path!("health" / "live").map({
+        let consumer = consumer.clone();
+        move || {
+            let (status, body) = consumer.live();
+            warp::reply::with_status(warp::reply::json(&body), status)
+        }
+    });
+    let ready = warp::path!("health" / "ready").map(move || {
+        let (status, body) = consumer.ready();
+        warp::reply::with_status(warp::reply::json(&body), status)
+    });
+
+    warp::get().and(health.or(live).or(ready))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_HASH: &str = "0x0000000000000000000000000000000000000000000000000000000000000064";
+    const TX_HASH: &str = "0x6464646464646464646464646464646464646464646464646464646464646464";
+
+    /// A transfer as block-monitor publishes it, minus the fields the ID
+    /// doesn't use.
+    fn transfer(fields: serde_json::Value) -> serde_json::Value {
+        let mut event = serde_json::json!({
+            "type": "transfer",
+            "stablecoin": "USDC",
+            "amount": "1.000000",
+            "from": "0x2222222222222222222222222222222222222222",
+            "to": "0x3333333333333333333333333333333333333333",
+            "block_number": 100,
+            "block_hash": BLOCK_HASH,
+            "tx_hash": TX_HASH,
+            "log_index": 3,
+            "chain_id": 8453,
+            "status": "pending",
+        });
+        for (key, value) in fields.as_object().unwrap() {
+            event[key] = value.clone();
+        }
+        event
+    }
+
+    fn dedup_key(event: serde_json::Value) -> Option<String> {
+        serde_json::from_value::<StreamEvent>(event)
+            .unwrap()
+            .dedup_key()
+    }
+
+    #[test]
+    fn dedup_keys_match_block_monitor_publication_ids() {
+        // block-monitor's keys_are_prefixed_publication_ids test expects the
+        // same IDs for the same event
+        assert_eq!(
+            dedup_key(transfer(serde_json::json!({}))).unwrap(),
+            format!("8453:transfer:pending:{}:{}:3", BLOCK_HASH, TX_HASH)
+        );
+        assert_eq!(
+            dedup_key(transfer(serde_json::json!({ "status": "confirmed" }))).unwrap(),
+            format!("8453:transfer:confirmed:{}:{}:3", BLOCK_HASH, TX_HASH)
+        );
+        assert_eq!(
+            dedup_key(transfer(serde_json::json!({ "type": "retracted" }))).unwrap(),
+            format!("8453:retracted:pending:{}:{}:3", BLOCK_HASH, TX_HASH)
+        );
+    }
 
-    info!("Health check server starting on port {}", port);
-    warp::serve(health).run(([0, 0, 0, 0], port)).await;
+    #[test]
+    fn entries_from_older_block_monitors_have_no_dedup_key() {
+        for field in ["log_index", "block_hash", "chain_id", "status"] {
+            let mut event = transfer(serde_json::json!({}));
+            event.as_object_mut().unwrap().remove(field);
+            assert_eq!(dedup_key(event), None, "{}", field);
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_asks_clients_to_reconnect() {
+        use tokio_tungstenite::tungstenite::Message as ClientMessage;
+
+        let clients: Clients = Arc::new(RwLock::new(HashMap::new()));
+        let connections = Connections::default();
+        let (addr, server) = warp::serve(websocket_route(clients.clone(), connections.clone()))
+            .bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr))
+            .await
+            .unwrap();
+
+        // Registered once the upgrade has gone through
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while clients.read().await.is_empty() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let event = StreamEvent::Admin(AdminEventData {
+            stablecoin: "USDC".to_string(),
+            action: "pause".to_string(),
+            account: None,
+            amount: None,
+            block_number: 1,
+            tx_hash: "0xabc".to_string(),
+            timestamp: None,
+            block_hash: None,
+            tx_index: None,
+            log_index: None,
+            chain_id: None,
+            status: None,
+        });
+        broadcast_to_clients(&clients, &event).await;
+        close_clients(&clients).await;
+
+        // Anything already broadcast arrives before the close frame
+        let Some(Ok(ClientMessage::Text(text))) = client.next().await else {
+            panic!("expected the broadcast event");
+        };
+        assert!(text.contains("\"action\":\"pause\""));
+        let Some(Ok(ClientMessage::Close(Some(frame)))) = client.next().await else {
+            panic!("expected a close frame");
+        };
+        assert_eq!(u16::from(frame.code), RESTART_CLOSE_CODE);
+        assert_eq!(frame.reason, "server restarting, reconnect");
+
+        // The connection stops counting once its close frame is out
+        tokio::time::timeout(Duration::from_secs(5), connections.closed())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn health_checks_share_the_websocket_routes() {
+        let health = Arc::new(ConsumerHealth::new(Duration::from_secs(30)));
+        let routes = websocket_route(Clients::default(), Connections::default())
+            .or(health_routes(health.clone()));
+
+        for path in ["/health", "/health/live", "/health/ready"] {
+            let response = warp::test::request().path(path).reply(&routes).await;
+            assert_eq!(response.status(), 200, "{}", path);
+        }
+
+        // Losing Redis fails readiness but not liveness
+        health.beat(false);
+        let live = warp::test::request()
+            .path("/health/live")
+            .reply(&routes)
+            .await;
+        assert_eq!(live.status(), 200);
+        let ready = warp::test::request()
+            .path("/health/ready")
+            .reply(&routes)
+            .await;
+        assert_eq!(ready.status(), 503);
+        let body: serde_json::Value = serde_json::from_slice(ready.body()).unwrap();
+        assert_eq!(body["checks"]["redis"]["ok"], false);
+    }
 }